@@ -0,0 +1,214 @@
+use regex::bytes::Regex;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+// 一致した箇所を強調表示するエスケープシーケンス
+const HIGHLIGHT_BEGIN: &[u8] = b"\x1b[1;31m";
+const HIGHLIGHT_END: &[u8] = b"\x1b[0m";
+
+// 先頭にNULを含むファイルはバイナリファイルとみなして読み飛ばす
+const BINARY_DETECTION_SIZE: usize = 8192;
+
+/// 一致する行の探し方と、出力の設定
+#[derive(Debug, Clone, Default)]
+pub struct GrepOptions {
+    /// 一致した行の前に出力する行数
+    pub before_context: usize,
+    /// 一致した行の後に出力する行数
+    pub after_context: usize,
+    /// 一致した箇所を強調表示する
+    pub color: bool,
+    /// すべての正規表現に一致する行だけを出力する。`false`ではいずれかに一致する行を出力する。
+    pub match_all: bool,
+}
+
+/// 入力を1行ずつ読み、正規表現に一致する行を`path:line:col:text`の形式で出力する
+///
+/// 前後の行は`path-line-text`の形式で出力し、離れた行の間には`--`を挟む。
+/// 行番号と列番号は1から数え、列番号は行頭から最初に一致した箇所までの文字数で表す。
+pub struct Grep {
+    regexes: Vec<Regex>,
+    options: GrepOptions,
+}
+
+impl Grep {
+    pub fn new(regexes: Vec<Regex>, options: GrepOptions) -> Grep {
+        Grep { regexes, options }
+    }
+
+    /// 1つの入力を検索し、一致した行の数を返す。バイナリファイルは何も出力せずに0を返す。
+    pub fn search<R: BufRead, W: Write>(&self, path: &str, mut reader: R, out: &mut W) -> io::Result<usize> {
+        let head = reader.fill_buf()?;
+        if head[..head.len().min(BINARY_DETECTION_SIZE)].contains(&0) {
+            return Ok(0);
+        }
+
+        let mut matched_lines = 0;
+        let mut before: VecDeque<(usize, Vec<u8>)> = VecDeque::with_capacity(self.options.before_context);
+        // 後ろに続けて出力する残りの行数
+        let mut after = 0;
+        // 最後に出力した行の番号
+        let mut last_printed: Option<usize> = None;
+        let mut buffer = Vec::new();
+        let mut line_number = 0;
+        loop {
+            buffer.clear();
+            if reader.read_until(b'\n', &mut buffer)? == 0 {
+                break;
+            }
+            line_number += 1;
+            let line = trim_line_end(&buffer);
+            match self.find(line) {
+                Some(ranges) => {
+                    matched_lines += 1;
+                    let first = before.front().map_or(line_number, |(n, _)| *n);
+                    let has_context = self.options.before_context > 0 || self.options.after_context > 0;
+                    if has_context && last_printed.is_some_and(|last| last + 1 < first) {
+                        out.write_all(b"--\n")?;
+                    }
+                    for (n, context) in before.drain(..) {
+                        self.write_line(out, path, n, None, &context, &[])?;
+                    }
+                    self.write_line(out, path, line_number, Some(column(line, &ranges)), line, &ranges)?;
+                    last_printed = Some(line_number);
+                    after = self.options.after_context;
+                }
+                None if after > 0 => {
+                    self.write_line(out, path, line_number, None, line, &[])?;
+                    last_printed = Some(line_number);
+                    after -= 1;
+                }
+                None if self.options.before_context > 0 => {
+                    if before.len() == self.options.before_context {
+                        before.pop_front();
+                    }
+                    before.push_back((line_number, line.to_vec()));
+                }
+                None => {}
+            }
+        }
+        Ok(matched_lines)
+    }
+
+    // 行が一致していれば、一致した箇所を重ならないように並べて返す
+    fn find(&self, line: &[u8]) -> Option<Vec<Range<usize>>> {
+        let is_match = if self.options.match_all {
+            self.regexes.iter().all(|regex| regex.is_match(line))
+        } else {
+            self.regexes.iter().any(|regex| regex.is_match(line))
+        };
+        if !is_match {
+            return None;
+        }
+        let mut ranges: Vec<Range<usize>> = self
+            .regexes
+            .iter()
+            .flat_map(|regex| regex.find_iter(line).map(|m| m.range()))
+            .filter(|range| !range.is_empty())
+            .collect();
+        ranges.sort_by_key(|range| (range.start, range.end));
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        Some(merged)
+    }
+
+    fn write_line<W: Write>(
+        &self,
+        out: &mut W,
+        path: &str,
+        line_number: usize,
+        column: Option<usize>,
+        line: &[u8],
+        ranges: &[Range<usize>],
+    ) -> io::Result<()> {
+        match column {
+            Some(column) => write!(out, "{}:{}:{}:", path, line_number, column)?,
+            None => write!(out, "{}-{}-", path, line_number)?,
+        }
+        if !self.options.color || ranges.is_empty() {
+            out.write_all(line)?;
+        } else {
+            let mut position = 0;
+            for range in ranges {
+                out.write_all(&line[position..range.start])?;
+                out.write_all(HIGHLIGHT_BEGIN)?;
+                out.write_all(&line[range.clone()])?;
+                out.write_all(HIGHLIGHT_END)?;
+                position = range.end;
+            }
+            out.write_all(&line[position..])?;
+        }
+        out.write_all(b"\n")
+    }
+}
+
+fn trim_line_end(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn column(line: &[u8], ranges: &[Range<usize>]) -> usize {
+    let start = ranges.first().map_or(0, |range| range.start);
+    String::from_utf8_lossy(&line[..start]).chars().count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(patterns: &[&str], options: GrepOptions, input: &str) -> (usize, String) {
+        let regexes = patterns.iter().map(|p| Regex::new(p).unwrap()).collect();
+        let grep = Grep::new(regexes, options);
+        let mut out = Vec::new();
+        let count = grep.search("a.txt", input.as_bytes(), &mut out).unwrap();
+        (count, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn prints_path_line_and_column() {
+        let (count, out) = run(
+            &["(?:検索|けんさく)"],
+            GrepOptions::default(),
+            "一行目\r\n全文検索の話\nけんさくと検索\n",
+        );
+        assert_eq!(count, 2);
+        assert_eq!(out, "a.txt:2:3:全文検索の話\na.txt:3:1:けんさくと検索\n");
+    }
+
+    #[test]
+    fn prints_context_lines() {
+        let options = GrepOptions {
+            before_context: 1,
+            after_context: 1,
+            ..GrepOptions::default()
+        };
+        let (_, out) = run(&["x"], options, "a\nb\nx\nc\nd\ne\nx\nx\n");
+        assert_eq!(
+            out,
+            "a.txt-2-b\na.txt:3:1:x\na.txt-4-c\n--\na.txt-6-e\na.txt:7:1:x\na.txt:8:1:x\n"
+        );
+    }
+
+    #[test]
+    fn highlights_matches_and_requires_all_patterns() {
+        let options = GrepOptions {
+            color: true,
+            match_all: true,
+            ..GrepOptions::default()
+        };
+        let (count, out) = run(&["検索", "辞書"], options, "検索\n辞書で検索\n");
+        assert_eq!(count, 1);
+        assert_eq!(out, "a.txt:2:1:\x1b[1;31m辞書\x1b[0mで\x1b[1;31m検索\x1b[0m\n");
+    }
+
+    #[test]
+    fn skips_binary_input() {
+        assert_eq!(run(&["x"], GrepOptions::default(), "x\0x\n"), (0, String::new()));
+    }
+}
@@ -3,28 +3,184 @@ pub mod migemo;
 // WASM用のコードブロック
 #[cfg(feature = "wasm")]
 mod wasm_exports {
-    use super::migemo::{compact_dictionary::CompactDictionary, query::query, regex_generator::RegexOperator};
+    use super::migemo::{self, matcher, query, romaji_scheme::RomajiScheme};
     use wasm_bindgen::prelude::*;
 
+    /// 検索語をどのように展開するかの設定
+    #[wasm_bindgen]
+    #[derive(Debug, Default)]
+    pub struct QueryOptions {
+        options: query::QueryOptions,
+    }
+
+    #[wasm_bindgen]
+    impl QueryOptions {
+        #[wasm_bindgen(constructor)]
+        pub fn new() -> QueryOptions {
+            QueryOptions::default()
+        }
+
+        #[wasm_bindgen(setter)]
+        pub fn set_input(&mut self, enabled: bool) {
+            self.options.input = enabled;
+        }
+
+        #[wasm_bindgen(setter = fullWidth)]
+        pub fn set_full_width(&mut self, enabled: bool) {
+            self.options.full_width = enabled;
+        }
+
+        #[wasm_bindgen(setter = halfWidth)]
+        pub fn set_half_width(&mut self, enabled: bool) {
+            self.options.half_width = enabled;
+        }
+
+        #[wasm_bindgen(setter)]
+        pub fn set_hiragana(&mut self, enabled: bool) {
+            self.options.hiragana = enabled;
+        }
+
+        #[wasm_bindgen(setter)]
+        pub fn set_katakana(&mut self, enabled: bool) {
+            self.options.katakana = enabled;
+        }
+
+        #[wasm_bindgen(setter = halfWidthKatakana)]
+        pub fn set_half_width_katakana(&mut self, enabled: bool) {
+            self.options.half_width_katakana = enabled;
+        }
+
+        #[wasm_bindgen(setter)]
+        pub fn set_dictionary(&mut self, enabled: bool) {
+            self.options.dictionary = enabled;
+        }
+
+        #[wasm_bindgen(setter)]
+        pub fn set_predictive(&mut self, enabled: bool) {
+            self.options.predictive = enabled;
+        }
+
+        /// `undefined`を指定すると上限をなくす
+        #[wasm_bindgen(setter = maxDictionaryHits)]
+        pub fn set_max_dictionary_hits(&mut self, max: Option<u32>) {
+            self.options.max_dictionary_hits = max.map(|max| max as usize);
+        }
+
+        /// `undefined`を指定すると上限をなくす
+        #[wasm_bindgen(setter = maxCandidates)]
+        pub fn set_max_candidates(&mut self, max: Option<u32>) {
+            self.options.max_candidates = max.map(|max| max as usize);
+        }
+
+        /// ローマ字の区切り方を何通りまで試すか。既定では1。
+        #[wasm_bindgen(setter = maxRomajiReadings)]
+        pub fn set_max_romaji_readings(&mut self, max: u32) {
+            self.options.max_romaji_readings = max as usize;
+        }
+
+        /// 綴り誤りを何文字まで許して辞書を引くか。既定では0で、近似検索をしない。
+        #[wasm_bindgen(setter = maxEdits)]
+        pub fn set_max_edits(&mut self, max: u32) {
+            self.options.max_edits = max as usize;
+        }
+
+        /// `undefined`を指定すると上限をなくす
+        #[wasm_bindgen(setter = maxPatternLength)]
+        pub fn set_max_pattern_length(&mut self, max: Option<u32>) {
+            self.options.max_pattern_length = max.map(|max| max as usize);
+        }
+    }
+
+    /// テキストの中で検索語に一致した箇所。位置はJavaScriptの文字列と同じUTF-16の添字で表す。
+    #[wasm_bindgen]
+    #[derive(Debug, Clone)]
+    pub struct Match {
+        start: u32,
+        end: u32,
+        candidate: String,
+    }
+
+    #[wasm_bindgen]
+    impl Match {
+        #[wasm_bindgen(getter)]
+        pub fn start(&self) -> u32 {
+            self.start
+        }
+
+        #[wasm_bindgen(getter)]
+        pub fn end(&self) -> u32 {
+            self.end
+        }
+
+        /// 一致した候補の文字列
+        #[wasm_bindgen(getter)]
+        pub fn candidate(&self) -> String {
+            self.candidate.clone()
+        }
+    }
+
+    impl From<matcher::Match> for Match {
+        fn from(m: matcher::Match) -> Match {
+            Match {
+                start: m.utf16.start as u32,
+                end: m.utf16.end as u32,
+                candidate: m.candidate.text,
+            }
+        }
+    }
+
     #[wasm_bindgen]
-    #[derive(Debug)]
     pub struct Migemo {
-        dictionary: CompactDictionary,
+        migemo: migemo::Migemo<'static>,
     }
 
     #[wasm_bindgen]
     impl Migemo {
         #[wasm_bindgen(constructor)]
-        pub fn new(buffer: &[u8]) -> Migemo {
-            let vec = Vec::from(buffer);
-            Migemo {
-                dictionary: CompactDictionary::new(&vec),
-            }
+        pub fn new(buffer: Vec<u8>) -> Result<Migemo, JsError> {
+            let migemo = migemo::Migemo::builder().dictionary_bytes(buffer).build()?;
+            Ok(Migemo { migemo })
+        }
+
+        /// ローマ字の入力方式 (`default`, `azik`, `act`, `kunrei`, `hepburn`) を指定して作る
+        #[wasm_bindgen(js_name = withRomajiScheme)]
+        pub fn with_romaji_scheme(buffer: Vec<u8>, scheme: &str) -> Result<Migemo, JsError> {
+            let scheme: RomajiScheme = scheme.parse().map_err(|e: String| JsError::new(&e))?;
+            let migemo = migemo::Migemo::builder()
+                .dictionary_bytes(buffer)
+                .romaji_processor(scheme.processor())
+                .build()?;
+            Ok(Migemo { migemo })
         }
 
         pub fn query(&self, word: String) -> String {
-            let rxop = RegexOperator::Default;
-            query(word, &self.dictionary, &rxop)
+            self.migemo.query(&word)
+        }
+
+        #[wasm_bindgen(js_name = queryWithOptions)]
+        pub fn query_with_options(&self, word: String, options: &QueryOptions) -> String {
+            self.migemo.query_with_options(&word, &options.options)
+        }
+
+        /// テキストに、検索語のトークンがこの順に並んでいる箇所があるかどうか
+        #[wasm_bindgen(js_name = isMatch)]
+        pub fn is_match(&self, text: &str, word: &str) -> bool {
+            self.migemo.is_match(text, word)
+        }
+
+        /// テキストの中で検索語に一致した箇所を、先頭から順に返す
+        #[wasm_bindgen(js_name = findIter)]
+        pub fn find_iter(&self, text: &str, word: &str) -> Vec<Match> {
+            self.find_iter_with_options(text, word, &QueryOptions::default())
+        }
+
+        #[wasm_bindgen(js_name = findIterWithOptions)]
+        pub fn find_iter_with_options(&self, text: &str, word: &str, options: &QueryOptions) -> Vec<Match> {
+            self.migemo
+                .find_iter_with_options(text, word, &options.options)
+                .into_iter()
+                .map(Match::from)
+                .collect()
         }
     }
 }
@@ -35,69 +191,203 @@ pub use wasm_exports::*;
 // Windows DLL用のコードブロック
 #[cfg(feature = "windows-dll")]
 mod windows_exports {
-    use super::migemo::compact_dictionary::*;
-    // インポートするquery関数に`migemo_query`という別名を付けます
-    use super::migemo::query::query as migemo_query;
-    use super::migemo::regex_generator::*;
+    // ライブラリの`Migemo`に`NativeMigemo`という別名を付けます
+    use super::migemo::Migemo as NativeMigemo;
+    use super::migemo::query::QueryOptions;
+    use super::migemo::romaji_scheme::RomajiScheme;
 
     use std::ffi::CString;
     use std::os::raw::c_char;
 
+    /// 検索語をどのように展開するかの設定。`max_`で始まる項目が0の場合は上限をなくす。
+    #[repr(C)]
+    pub struct MigemoQueryOptions {
+        pub input: bool,
+        pub full_width: bool,
+        pub half_width: bool,
+        pub hiragana: bool,
+        pub katakana: bool,
+        pub half_width_katakana: bool,
+        pub dictionary: bool,
+        pub predictive: bool,
+        pub max_dictionary_hits: u32,
+        pub max_candidates: u32,
+        pub max_pattern_length: u32,
+        /// ローマ字の区切り方を何通りまで試すか。0は1と同じ。
+        pub max_romaji_readings: u32,
+        /// 綴り誤りを何文字まで許して辞書を引くか。0では近似検索をしない。
+        pub max_edits: u32,
+    }
+
+    // 0を上限なしとして扱う
+    fn limit(max: u32) -> Option<usize> {
+        match max {
+            0 => None,
+            max => Some(max as usize),
+        }
+    }
+
+    impl MigemoQueryOptions {
+        fn to_query_options(&self) -> QueryOptions {
+            QueryOptions {
+                input: self.input,
+                full_width: self.full_width,
+                half_width: self.half_width,
+                hiragana: self.hiragana,
+                katakana: self.katakana,
+                half_width_katakana: self.half_width_katakana,
+                dictionary: self.dictionary,
+                predictive: self.predictive,
+                max_dictionary_hits: limit(self.max_dictionary_hits),
+                max_candidates: limit(self.max_candidates),
+                max_pattern_length: limit(self.max_pattern_length),
+                max_romaji_readings: self.max_romaji_readings.max(1) as usize,
+                max_edits: self.max_edits as usize,
+                ..QueryOptions::default()
+            }
+        }
+    }
+
+    /// 既定の設定を返す
+    #[unsafe(no_mangle)]
+    pub extern "C" fn default_query_options() -> MigemoQueryOptions {
+        let options = QueryOptions::default();
+        MigemoQueryOptions {
+            input: options.input,
+            full_width: options.full_width,
+            half_width: options.half_width,
+            hiragana: options.hiragana,
+            katakana: options.katakana,
+            half_width_katakana: options.half_width_katakana,
+            dictionary: options.dictionary,
+            predictive: options.predictive,
+            max_dictionary_hits: 0,
+            max_candidates: 0,
+            max_pattern_length: 0,
+            max_romaji_readings: options.max_romaji_readings as u32,
+            max_edits: options.max_edits as u32,
+        }
+    }
+
     #[repr(C, align(8))]
     pub struct Migemo {
-        dict: *mut CompactDictionary,
+        inner: *mut NativeMigemo<'static>,
         result_ptr: *mut c_char,
         result_len: u32,
     }
 
+    /// 辞書の読み込みに失敗した場合は、`inner`がNULLのMigemoを返す
+    ///
+    /// # Safety
+    /// `buffer`は`len`バイトの辞書を指していなければならない。
     #[unsafe(no_mangle)]
     pub unsafe extern "C" fn load(buffer: *const u8, len: u32) -> Migemo {
-        let mut dst = Vec::with_capacity(len as usize);
-        dst.set_len(len as usize);
-        std::ptr::copy(buffer, dst.as_mut_ptr(), len as usize);
-        let dict = Box::new(CompactDictionary::new(&dst));
+        unsafe { load_with_romaji_scheme(buffer, len, 0) }
+    }
+
+    /// ローマ字の入力方式を指定して辞書を読み込む
+    ///
+    /// `scheme`は0が一般的なローマ字入力、1がAZIK、2がACT、3が訓令式、4がヘボン式。
+    /// 辞書の読み込みに失敗した場合や、`scheme`が範囲外の場合は、`inner`がNULLのMigemoを返す。
+    ///
+    /// # Safety
+    /// `buffer`は`len`バイトの辞書を指していなければならない。
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn load_with_romaji_scheme(buffer: *const u8, len: u32, scheme: u32) -> Migemo {
+        let scheme = match scheme {
+            0 => Some(RomajiScheme::Default),
+            1 => Some(RomajiScheme::Azik),
+            2 => Some(RomajiScheme::Act),
+            3 => Some(RomajiScheme::Kunrei),
+            4 => Some(RomajiScheme::Hepburn),
+            _ => None,
+        };
+        // 呼び出し側は読み込んだ後にバッファを解放してよいので、一度だけコピーして持つ
+        let src = unsafe { std::slice::from_raw_parts(buffer, len as usize) };
+        let built = scheme.map(|scheme| {
+            NativeMigemo::builder()
+                .dictionary_bytes(src)
+                .romaji_processor(scheme.processor())
+                .build()
+        });
+        let inner = match built {
+            Some(Ok(migemo)) => Box::into_raw(Box::new(migemo)),
+            _ => std::ptr::null_mut(),
+        };
         return Migemo {
-            dict: Box::into_raw(dict),
+            inner,
             result_ptr: 0 as *mut c_char,
             result_len: 0,
         };
     }
 
+    /// 既定の設定で検索語を展開し、結果を`result_ptr`と`result_len`に格納する
+    ///
+    /// # Safety
+    /// `migemo`は`load`が返したMigemoを、`buffer`は`len`バイトの文字列を指していなければならない。
     #[unsafe(no_mangle)]
     pub unsafe extern "C" fn query(migemo: *mut Migemo, buffer: *const u8, len: u32) -> bool {
-        if !(*migemo).result_ptr.is_null() {
-            let _ = CString::from_raw((*migemo).result_ptr);
-            (*migemo).result_ptr = std::ptr::null_mut();
-            (*migemo).result_len = 0;
+        unsafe { query_impl(migemo, buffer, len, &QueryOptions::default()) }
+    }
+
+    /// `options`がNULLの場合は既定の設定を使う
+    ///
+    /// # Safety
+    /// `migemo`は`load`が返したMigemoを、`buffer`は`len`バイトの文字列を指していなければならない。
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn query_with_options(
+        migemo: *mut Migemo,
+        buffer: *const u8,
+        len: u32,
+        options: *const MigemoQueryOptions,
+    ) -> bool {
+        let options = match unsafe { options.as_ref() } {
+            Some(options) => options.to_query_options(),
+            None => QueryOptions::default(),
+        };
+        unsafe { query_impl(migemo, buffer, len, &options) }
+    }
+
+    unsafe fn query_impl(migemo: *mut Migemo, buffer: *const u8, len: u32, options: &QueryOptions) -> bool {
+        let migemo = unsafe { &mut *migemo };
+        unsafe { free_result(migemo) };
+        if migemo.inner.is_null() {
+            return false;
         }
 
-        let mut dst = Vec::with_capacity(len as usize);
-        dst.set_len(len as usize);
-        std::ptr::copy(buffer, dst.as_mut_ptr(), len as usize);
-        let string = String::from_utf8_lossy(&dst);
+        let src = unsafe { std::slice::from_raw_parts(buffer, len as usize) };
+        let string = String::from_utf8_lossy(src);
 
-        let dict = &*(*migemo).dict;
-        let rxop = RegexOperator::Default;
-        // 別名を付けた`migemo_query`関数を呼び出します
-        let result = migemo_query(string.to_string(), dict, &rxop);
+        let inner = unsafe { &*migemo.inner };
+        let result = inner.query_with_options(&string, options);
 
         let c_string = CString::new(result).unwrap();
-        (*migemo).result_len = c_string.as_bytes().len() as u32;
-        (*migemo).result_ptr = c_string.into_raw();
+        migemo.result_len = c_string.as_bytes().len() as u32;
+        migemo.result_ptr = c_string.into_raw();
 
         return true;
     }
 
+    // 前回の検索結果を解放する
+    unsafe fn free_result(migemo: &mut Migemo) {
+        if !migemo.result_ptr.is_null() {
+            let _ = unsafe { CString::from_raw(migemo.result_ptr) };
+            migemo.result_ptr = std::ptr::null_mut();
+            migemo.result_len = 0;
+        }
+    }
+
+    /// 検索結果と辞書を解放する。解放したMigemoは再び`query`に渡さない。
+    ///
+    /// # Safety
+    /// `migemo`は`load`が返したMigemoを指していなければならない。
     #[unsafe(no_mangle)]
     pub unsafe extern "C" fn destroy(migemo: *mut Migemo) {
-        if !(*migemo).result_ptr.is_null() {
-            let _ = CString::from_raw((*migemo).result_ptr);
-            (*migemo).result_ptr = std::ptr::null_mut();
-            (*migemo).result_len = 0;
-        }
-        if !(*migemo).dict.is_null() {
-            let _ = Box::from_raw((*migemo).dict);
-            (*migemo).dict = std::ptr::null_mut();
+        let migemo = unsafe { &mut *migemo };
+        unsafe { free_result(migemo) };
+        if !migemo.inner.is_null() {
+            let _ = unsafe { Box::from_raw(migemo.inner) };
+            migemo.inner = std::ptr::null_mut();
         }
     }
 }
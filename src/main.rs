@@ -2,25 +2,432 @@ extern crate rustmigemo;
 use std::env;
 use std::fs::File;
 use std::io;
-use std::io::Read;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
+use std::str::FromStr;
 
-use pico_args::Arguments;
+use ignore::WalkBuilder;
+use pico_args::{Arguments, Keys};
+use rustmigemo::migemo::Migemo;
 use rustmigemo::migemo::compact_dictionary::*;
+use rustmigemo::migemo::compact_dictionary_builder::*;
 use rustmigemo::migemo::query::*;
 use rustmigemo::migemo::regex_generator::*;
+use rustmigemo::migemo::romaji_processor::RomajiProcessor;
+use rustmigemo::migemo::romaji_scheme::RomajiScheme;
+use rustmigemo::migemo::skk_dictionary::*;
+use rustmigemo::migemo::text_dictionary::*;
+
+mod grep;
+use grep::{Grep, GrepOptions};
 
 fn print_usage(program: &str) {
     let brief = format!("Usage: {} [options]", program);
     println!("{}", brief);
+    println!("       {} convert [options] <input> <output>", program);
+    println!("       {} dump <dict> [<output>]", program);
+    println!("       {} grep [options] <word> [<path>...]", program);
     println!("\nOptions:");
     println!("  -d, --dict <dict>    Use a file <dict> for dictionary. (default: migemo-compact-dict)");
+    println!("                       Repeat to layer several dictionaries in the given order.");
     println!("  -q, --quiet          Show no message except results.");
     println!("  -v, --vim            Use vim style regexp.");
     println!("  -e, --emacs          Use emacs style regexp.");
     println!("  -n, --nonewline      Don't use newline match.");
+    println!("      --dialect <name> Regexp dialect: default, vim, emacs, rust (ripgrep), pcre, ecmascript,");
+    println!("                       dotnet or ere. (default: default)");
+    println!("      --whitespace     Match any whitespace, not only newlines, between characters.");
+    println!("      --romaji <scheme>  Romaji input scheme: default, azik, act, kunrei or hepburn.");
+    println!("                       (default: default)");
+    println!("      --romaji-table <file>  Convert romaji with a table in Google Japanese Input format");
+    println!("                       (\"<romaji>\\t<kana>[\\t<next input>]\" per line), such as AZIK.");
+    println!("      --mode <mode>    How to search several words: phrase, any or all. (default: phrase)");
+    println!("                       With all, print one regexp per word; a match must satisfy all of them.");
     println!("  -w, --word <word>    Expand a <word> and soon exit.");
+    println!("      --no-dict        Don't look up the dictionary.");
+    println!("      --exact          Look up the dictionary by exact match instead of prefix match.");
+    println!("      --max-dict-hits <n>  Use at most <n> dictionary words per word.");
+    println!("      --max-candidates <n>  Use at most <n> candidates per word, dropping rare and long words first.");
+    println!("      --max-length <n> Keep the regexp for each word within <n> bytes, dropping candidates.");
+    println!("      --readings <n>   Try up to <n> ways to split romaji into kana, e.g. tenin as てにん and てんいん.");
+    println!("                       (default: 1)");
+    println!("      --max-edits <n>  Also look up readings within <n> typos, e.g. kensku for 検索. (default: 0)");
+    println!("      --no-full-width  Don't add full-width variants.");
+    println!("      --no-half-width  Don't add half-width variants.");
+    println!("      --no-hiragana    Don't add hiragana variants.");
+    println!("      --no-katakana    Don't add katakana variants.");
+    println!("      --no-half-width-katakana  Don't add half-width katakana variants.");
     println!("  -h, --help           Show this message.");
+    println!("\nCommands:");
+    println!("  convert              Convert a text dictionary into a migemo-compact-dict.");
+    println!("      --format <fmt>   Format of <input>: migemo (C/Migemo) or skk (SKK-JISYO). (default: migemo)");
+    println!("      --encoding <enc> Encoding of <input>: auto, utf-8 or euc-jp. (default: auto)");
+    println!("      --okuri <policy> Okuri-ari entries in SKK-JISYO: drop, stem or expand. (default: stem)");
+    println!("      --header         Write a header with a format version and checksum.");
+    println!("      --weights <file> Store word weights read from <file> (\"<word>\\t<weight>\" per line),");
+    println!("                       so that frequent words come first. Implies --header.");
+    println!("  dump                 Write a migemo-compact-dict as a UTF-8 migemo-dict text file.");
+    println!("  grep                 Search files for lines matching <word>. Reads stdin without <path>.");
+    println!("                       Takes -d and the options for expanding words above.");
+    println!("      -r, --recursive  Search directories recursively.");
+    println!("      --no-ignore      Search files ignored by .gitignore, .ignore and so on.");
+    println!("      --hidden         Search hidden files and directories.");
+    println!("      -A, --after-context <n>   Print <n> lines after each match.");
+    println!("      -B, --before-context <n>  Print <n> lines before each match.");
+    println!("      -C, --context <n>         Print <n> lines before and after each match.");
+    println!("      --color <when>   Highlight matches: auto, always or never. (default: auto)");
+}
+
+// オプションの値を解析し、解析できなければメッセージを表示して終了する
+fn option_or_exit<T: FromStr>(args: &mut Arguments, key: impl Into<Keys>, default: T) -> T
+where
+    T::Err: std::fmt::Display,
+{
+    match args.opt_value_from_str(key) {
+        Ok(value) => value.unwrap_or(default),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// 省略できるオプションの値を解析し、解析できなければメッセージを表示して終了する
+fn optional_or_exit<T: FromStr>(args: &mut Arguments, key: impl Into<Keys>) -> Option<T>
+where
+    T::Err: std::fmt::Display,
+{
+    match args.opt_value_from_str(key) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// 辞書ファイルのパスを解析する。指定がなければ既定の辞書ファイルを使う
+fn dictionary_paths(args: &mut Arguments) -> Vec<String> {
+    let mut dictfiles: Vec<String> = args.values_from_str(["-d", "--dict"]).unwrap_or_default();
+    if dictfiles.is_empty() {
+        dictfiles.push("migemo-compact-dict".to_string());
+    }
+    dictfiles
+}
+
+// 検索語の展開に関するオプションを解析する
+fn query_options_or_exit(args: &mut Arguments) -> QueryOptions {
+    let max_dictionary_hits: Option<usize> = optional_or_exit(args, "--max-dict-hits");
+    let max_candidates: Option<usize> = optional_or_exit(args, "--max-candidates");
+    let max_pattern_length: Option<usize> = optional_or_exit(args, "--max-length");
+    let max_romaji_readings = option_or_exit(args, "--readings", 1usize);
+    let max_edits = option_or_exit(args, "--max-edits", 0usize);
+    QueryOptions::new()
+        .dictionary(!args.contains("--no-dict"))
+        .predictive(!args.contains("--exact"))
+        .max_dictionary_hits(max_dictionary_hits)
+        .max_candidates(max_candidates)
+        .max_pattern_length(max_pattern_length)
+        .max_romaji_readings(max_romaji_readings)
+        .max_edits(max_edits)
+        .full_width(!args.contains("--no-full-width"))
+        .half_width(!args.contains("--no-half-width"))
+        .hiragana(!args.contains("--no-hiragana"))
+        .katakana(!args.contains("--no-katakana"))
+        .half_width_katakana(!args.contains("--no-half-width-katakana"))
+        .whitespace(args.contains("--whitespace"))
+}
+
+// ローマ字の変換表を読み込む。指定がなければ組み込みの変換表を使う
+fn romaji_processor_or_exit(args: &mut Arguments) -> Option<RomajiProcessor> {
+    let scheme: Option<RomajiScheme> = optional_or_exit(args, "--romaji");
+    let path: Option<String> = optional_or_exit(args, "--romaji-table");
+    let path = match (scheme, path) {
+        (Some(_), Some(_)) => {
+            eprintln!("Specify either --romaji or --romaji-table");
+            std::process::exit(1);
+        }
+        (Some(scheme), None) => return Some(scheme.processor()),
+        (None, Some(path)) => path,
+        (None, None) => return None,
+    };
+    let bytes = std::fs::read(&path).expect("Fail to load romaji table");
+    let processor = decode_text(&bytes, TextEncoding::Auto)
+        .map_err(|e| e.to_string())
+        .and_then(|text| RomajiProcessor::from_table(&text).map_err(|e| e.to_string()));
+    match processor {
+        Ok(processor) => Some(processor),
+        Err(e) => {
+            eprintln!("Fail to load romaji table {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// 辞書ファイルをメモリにマップし、コピーせずに参照する。複数指定された場合は、指定した順に重ねる
+fn build_migemo(
+    dictfiles: &[String],
+    romaji: Option<RomajiProcessor>,
+    operator: RegexOperator,
+    options: QueryOptions,
+) -> Migemo<'static> {
+    let mut builder = Migemo::builder().operator(operator).options(options);
+    if let Some(romaji) = romaji {
+        builder = builder.romaji_processor(romaji);
+    }
+    for dictfile in dictfiles {
+        builder = builder.dictionary_path(dictfile);
+    }
+    match builder.build() {
+        Ok(migemo) => migemo,
+        Err(e) => {
+            eprintln!("Fail to load dict file {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn convert(mut args: Arguments) {
+    let encoding = option_or_exit(&mut args, "--encoding", TextEncoding::Auto);
+    let format = option_or_exit(&mut args, "--format", "migemo".to_string());
+    let okuri = option_or_exit(&mut args, "--okuri", OkuriAriPolicy::Stem);
+    let with_header = args.contains("--header");
+    let weights_path: Option<String> = optional_or_exit(&mut args, "--weights");
+    let paths: Result<(String, String), _> = args
+        .free_from_str()
+        .and_then(|input| args.free_from_str().map(|output| (input, output)));
+    let (input, output) = match paths {
+        Ok(paths) => paths,
+        Err(_) => {
+            eprintln!("Usage: convert [options] <input> <output>");
+            std::process::exit(1);
+        }
+    };
+    let remaining = args.finish();
+    if !remaining.is_empty() {
+        eprintln!("Warning: Unused arguments: {:?}", remaining);
+    }
+
+    let bytes = std::fs::read(&input).expect("Fail to load text dict file");
+    let dict = match format.as_str() {
+        "migemo" => read_migemo_dict(&bytes, encoding),
+        "skk" => read_skk_jisyo(&bytes, encoding, okuri).map(|(dict, report)| {
+            eprintln!(
+                "Imported {} entries (skipped {} readings, {} okuri-ari entries, {} candidates, {} malformed lines)",
+                report.imported_entries,
+                report.skipped_readings.len(),
+                report.dropped_okuri_ari,
+                report.skipped_candidates,
+                report.malformed_lines.len()
+            );
+            dict
+        }),
+        _ => {
+            eprintln!("Unknown format: {}", format);
+            std::process::exit(1);
+        }
+    };
+    let dict = match dict {
+        Ok(dict) => dict,
+        Err(e) => {
+            eprintln!("Fail to load text dict file: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let weights = weights_path.map(|path| {
+        let bytes = std::fs::read(&path).expect("Fail to load weights file");
+        match decode_text(&bytes, encoding) {
+            Ok(text) => parse_word_weights(&text),
+            Err(e) => {
+                eprintln!("Fail to load weights file: {}", e);
+                std::process::exit(1);
+            }
+        }
+    });
+    let (buffer, report) = build_with_report(dict, &BuildOptions { with_header, weights });
+    for key in &report.skipped_keys {
+        eprintln!("Skipped the reading with unsupported characters: {}", key);
+    }
+    std::fs::write(&output, buffer).expect("Fail to write dict file");
+}
+
+fn dump(mut args: Arguments) {
+    let input: String = match args.free_from_str() {
+        Ok(input) => input,
+        Err(_) => {
+            eprintln!("Usage: dump <dict> [<output>]");
+            std::process::exit(1);
+        }
+    };
+    let output: Option<String> = args.opt_free_from_str().unwrap_or(None);
+    let remaining = args.finish();
+    if !remaining.is_empty() {
+        eprintln!("Warning: Unused arguments: {:?}", remaining);
+    }
+
+    let bytes = std::fs::read(&input).expect("Fail to load dict file");
+    let dict = match CompactDictionaryRef::try_borrow_from_bytes(&bytes) {
+        Ok(dict) => dict,
+        Err(e) => {
+            eprintln!("Fail to load dict file: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // 差分を取りやすいように、読みの順に並べる
+    let mut entries: Vec<(String, Vec<String>)> = dict.entries().collect();
+    entries.sort();
+    let result = match output {
+        Some(path) => {
+            let mut writer = io::BufWriter::new(File::create(path).expect("Fail to create output file"));
+            write_migemo_dict(&mut writer, entries).and_then(|_| writer.flush())
+        }
+        None => {
+            let mut writer = io::BufWriter::new(io::stdout().lock());
+            write_migemo_dict(&mut writer, entries).and_then(|_| writer.flush())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Fail to write text dict file: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn grep(mut args: Arguments) {
+    let dictfiles = dictionary_paths(&mut args);
+    let options = query_options_or_exit(&mut args);
+    let romaji = romaji_processor_or_exit(&mut args);
+    let mode = option_or_exit(&mut args, "--mode", SearchMode::Phrase);
+    let recursive = args.contains(["-r", "--recursive"]);
+    let no_ignore = args.contains("--no-ignore");
+    let hidden = args.contains("--hidden");
+    let context = option_or_exit(&mut args, ["-C", "--context"], 0usize);
+    let after_context = option_or_exit(&mut args, ["-A", "--after-context"], context);
+    let before_context = option_or_exit(&mut args, ["-B", "--before-context"], context);
+    let color = option_or_exit(&mut args, "--color", "auto".to_string());
+    let color = match color.as_str() {
+        "auto" => io::stdout().is_terminal(),
+        "always" => true,
+        "never" => false,
+        _ => {
+            eprintln!("Unknown color mode: {}", color);
+            std::process::exit(2);
+        }
+    };
+    let word: String = match args.free_from_str() {
+        Ok(word) => word,
+        Err(_) => {
+            eprintln!("Usage: grep [options] <word> [<path>...]");
+            std::process::exit(2);
+        }
+    };
+    let mut paths: Vec<String> = Vec::new();
+    while let Ok(Some(path)) = args.opt_free_from_str() {
+        paths.push(path);
+    }
+    let remaining = args.finish();
+    if !remaining.is_empty() {
+        eprintln!("Warning: Unused arguments: {:?}", remaining);
+    }
+
+    // 行ごとに検索するので、文字の間の改行は考えなくてよい
+    let migemo = build_migemo(&dictfiles, romaji, RegexOperator::RustNonNewline, options);
+    let patterns = migemo.query_with_report(&word, mode).patterns;
+    if patterns.is_empty() {
+        eprintln!("Empty word");
+        std::process::exit(2);
+    }
+    let regexes = match patterns.iter().map(|p| regex::bytes::Regex::new(p)).collect() {
+        Ok(regexes) => regexes,
+        Err(e) => {
+            eprintln!("Fail to compile the regexp: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let grep = Grep::new(
+        regexes,
+        GrepOptions {
+            before_context,
+            after_context,
+            color,
+            match_all: mode == SearchMode::All,
+        },
+    );
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    let mut matched = false;
+    let mut failed = false;
+    // 読み込みに失敗した場合は`false`を返す
+    let mut search = |path: &str, reader: &mut dyn io::BufRead| match grep.search(path, reader, &mut out) {
+        Ok(count) => {
+            matched |= count > 0;
+            true
+        }
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            false
+        }
+    };
+    if paths.is_empty() && !recursive {
+        failed |= !search("(standard input)", &mut io::stdin().lock());
+    } else {
+        // パスを指定せずに再帰的に検索する場合は、カレントディレクトリからの相対パスで表示する
+        let relative = paths.is_empty();
+        if relative {
+            paths.push(".".to_string());
+        }
+        for path in &paths {
+            let mut walker = WalkBuilder::new(path);
+            // `standard_filters`は隠しファイルの除外も切り替えるので、`--no-ignore`とは別に指定し直す
+            walker.standard_filters(!no_ignore).hidden(!hidden);
+            if !recursive {
+                walker.max_depth(Some(0));
+            }
+            for entry in walker.build() {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        failed = true;
+                        continue;
+                    }
+                };
+                if entry.file_type().is_some_and(|t| t.is_dir()) {
+                    if !recursive {
+                        eprintln!("{}: Is a directory", path);
+                        failed = true;
+                    }
+                    continue;
+                }
+                let path = match entry.path().strip_prefix(".") {
+                    Ok(stripped) if relative => stripped,
+                    _ => entry.path(),
+                };
+                let path = path.to_string_lossy();
+                match File::open(entry.path()) {
+                    Ok(f) => failed |= !search(&path, &mut io::BufReader::new(f)),
+                    Err(e) => {
+                        eprintln!("{}: {}", path, e);
+                        failed = true;
+                    }
+                }
+            }
+        }
+    }
+    drop(search);
+    if let Err(e) = out.flush()
+        && e.kind() != io::ErrorKind::BrokenPipe
+    {
+        eprintln!("{}", e);
+        failed = true;
+    }
+    // grepと同じく、一致した行があれば0、なければ1、エラーがあれば2で終了する
+    std::process::exit(if failed {
+        2
+    } else if matched {
+        0
+    } else {
+        1
+    });
 }
 
 fn main() {
@@ -36,16 +443,39 @@ fn main() {
         return;
     }
 
+    // サブコマンドが指定されている場合は、そちらを実行して終了
+    match args.subcommand() {
+        Ok(Some(command)) if command == "convert" => {
+            convert(args);
+            return;
+        }
+        Ok(Some(command)) if command == "dump" => {
+            dump(args);
+            return;
+        }
+        Ok(Some(command)) if command == "grep" => {
+            grep(args);
+            return;
+        }
+        Ok(Some(command)) => {
+            eprintln!("Unknown command: {}", command);
+            std::process::exit(1);
+        }
+        _ => {}
+    }
+
     // 各オプションを解析
     // エラーが発生した場合は、メッセージを表示して終了
-    let dictfile = args
-        .opt_value_from_str(["-d", "--dict"])
-        .unwrap_or(None)
-        .unwrap_or_else(|| "migemo-compact-dict".to_string());
+    let dictfiles = dictionary_paths(&mut args);
 
     let quiet = args.contains(["-q", "--quiet"]);
     let word: Option<String> = args.opt_value_from_str(["-w", "--word"]).unwrap_or(None);
 
+    let options = query_options_or_exit(&mut args);
+    let romaji = romaji_processor_or_exit(&mut args);
+
+    let mode = option_or_exit(&mut args, "--mode", SearchMode::Phrase);
+    let dialect: Option<String> = args.opt_value_from_str("--dialect").unwrap_or(None);
     let v = args.contains(["-v", "--vim"]);
     let e = args.contains(["-e", "--emacs"]);
     let n = args.contains(["-n", "--nonewline"]);
@@ -57,25 +487,35 @@ fn main() {
     }
 
     // 正規表現のオペレータを設定
-    let rxop = match (v, e, n) {
-        (true, false, false) => RegexOperator::Vim,
-        (true, false, true) => RegexOperator::VimNonNewline,
-        (false, true, false) => RegexOperator::Emacs,
-        (false, true, true) => RegexOperator::EmacsNonNewline,
-        (_, _, _) => RegexOperator::Default,
+    let rxop = match (dialect, v, e, n) {
+        (Some(dialect), _, _, _) => match RegexOperator::from_dialect(&dialect, !n) {
+            Some(rxop) => rxop,
+            None => {
+                eprintln!("Unknown dialect: {}", dialect);
+                std::process::exit(1);
+            }
+        },
+        (None, true, false, false) => RegexOperator::Vim,
+        (None, true, false, true) => RegexOperator::VimNonNewline,
+        (None, false, true, false) => RegexOperator::Emacs,
+        (None, false, true, true) => RegexOperator::EmacsNonNewline,
+        (None, _, _, _) => RegexOperator::Default,
     };
 
-    // 辞書ファイルを読み込み
-    let mut f = File::open(&dictfile).expect("Fail to load dict file");
-    let mut buf = Vec::new();
-    let _ = f.read_to_end(&mut buf);
-    drop(f);
-    let dict = CompactDictionary::new(&buf);
+    let migemo = build_migemo(&dictfiles, romaji, rxop, options);
 
     // --word オプションが指定されている場合
     if let Some(w) = word {
-        let result = query(w, &dict, &rxop);
-        println!("{}", result);
+        let report = migemo.query_with_report(&w, mode);
+        if report.is_truncated() && !quiet {
+            eprintln!(
+                "Warning: Dropped {} candidates to fit the limits",
+                report.dropped_candidates
+            );
+        }
+        for result in report.patterns {
+            println!("{}", result);
+        }
     // オプションがない場合は対話モード
     } else {
         loop {
@@ -88,11 +528,19 @@ fn main() {
             if line.trim().is_empty() {
                 break;
             }
-            let result = query(line.trim().to_string(), &dict, &rxop);
-            if !quiet {
-                println!("PATTERN: {}", result);
-            } else {
-                println!("{}", result);
+            let report = migemo.query_with_report(line.trim(), mode);
+            if report.is_truncated() && !quiet {
+                eprintln!(
+                    "Warning: Dropped {} candidates to fit the limits",
+                    report.dropped_candidates
+                );
+            }
+            for result in report.patterns {
+                if !quiet {
+                    println!("PATTERN: {}", result);
+                } else {
+                    println!("{}", result);
+                }
             }
         }
     }
@@ -0,0 +1,114 @@
+use byteorder::{BigEndian, ByteOrder};
+use std::marker::PhantomData;
+
+/// 整数の配列への読み取り専用のアクセス
+///
+/// `Vec`で所有している配列と、辞書ファイルのバイト列をコピーせずに参照する配列を、
+/// `BitVector`や`LoudsTrie`から同じように扱うために使う。
+pub trait ArrayView<T> {
+    fn len(&self) -> usize;
+
+    fn at(&self, index: usize) -> T;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn to_vec(&self) -> Vec<T> {
+        (0..self.len()).map(|i| self.at(i)).collect()
+    }
+}
+
+impl<T: Copy> ArrayView<T> for Vec<T> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    #[inline]
+    fn at(&self, index: usize) -> T {
+        self[index]
+    }
+}
+
+/// ビッグエンディアンで格納された整数の配列を、バイト列のまま参照する
+#[derive(Debug, Clone, Copy)]
+pub struct BigEndianArray<'a, T> {
+    bytes: &'a [u8],
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T> BigEndianArray<'a, T> {
+    pub fn new(bytes: &'a [u8]) -> BigEndianArray<'a, T> {
+        assert!(
+            bytes.len().is_multiple_of(std::mem::size_of::<T>()),
+            "Byte length {} is not a multiple of the element size {}.",
+            bytes.len(),
+            std::mem::size_of::<T>()
+        );
+        BigEndianArray {
+            bytes,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl ArrayView<u16> for BigEndianArray<'_, u16> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.bytes.len() / 2
+    }
+
+    #[inline]
+    fn at(&self, index: usize) -> u16 {
+        BigEndian::read_u16(&self.bytes[index * 2..index * 2 + 2])
+    }
+}
+
+impl ArrayView<u32> for BigEndianArray<'_, u32> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.bytes.len() / 4
+    }
+
+    #[inline]
+    fn at(&self, index: usize) -> u32 {
+        BigEndian::read_u32(&self.bytes[index * 4..index * 4 + 4])
+    }
+}
+
+impl ArrayView<u64> for BigEndianArray<'_, u64> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.bytes.len() / 8
+    }
+
+    #[inline]
+    fn at(&self, index: usize) -> u64 {
+        BigEndian::read_u64(&self.bytes[index * 8..index * 8 + 8])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn big_endian_array_reads_in_place() {
+        let bytes = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
+        let u16s = BigEndianArray::<u16>::new(&bytes);
+        assert_eq!(u16s.len(), 4);
+        assert_eq!(u16s.to_vec(), vec![0x0001, 0x0203, 0x0405, 0x0607]);
+        let u32s = BigEndianArray::<u32>::new(&bytes);
+        assert_eq!(u32s.to_vec(), vec![0x00010203, 0x04050607]);
+        let u64s = BigEndianArray::<u64>::new(&bytes);
+        assert_eq!(u64s.at(0), 0x0001020304050607);
+    }
+
+    #[test]
+    #[should_panic]
+    fn big_endian_array_rejects_partial_element() {
+        let bytes = [0x00, 0x01, 0x02];
+        BigEndianArray::<u16>::new(&bytes);
+    }
+}
@@ -1,5 +1,7 @@
+use super::array_view::ArrayView;
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::_pdep_u64;
+use std::sync::OnceLock;
 
 const SELECT8: [[u8; 8]; 256] = build_select8();
 
@@ -21,28 +23,36 @@ const fn build_select8() -> [[u8; 8]; 256] {
     table
 }
 
+/// rank/select辞書を備えたビットベクトル
+///
+/// `W`はビット列を格納するワードの配列で、`Vec<u64>`の代わりに辞書ファイルのバイト列を直接参照することもできる。
+/// rank/selectの索引は、最初にrankかselectを呼んだときに作る。
 #[derive(Debug)]
-pub struct BitVector {
-    words: Vec<u64>,
+pub struct BitVector<W = Vec<u64>> {
+    words: W,
     size_in_bits: usize,
+    directory: OnceLock<RankDirectory>,
+}
+
+// 512ビットごとの1の数の累計 (lb) と、その中での64ビットごとの累計 (sb)
+#[derive(Debug)]
+struct RankDirectory {
     lb: Vec<u32>,
     sb: Vec<u16>,
 }
 
-impl BitVector {
-    pub fn new(words: Vec<u64>, size_in_bits: usize) -> BitVector {
-        assert!(
-            (size_in_bits + 63) / 64 == words.len(),
-            "Word vector length does not match the size in bits. Expected {}, got {}.",
-            (size_in_bits + 63) / 64,
-            words.len()
-        );
+impl RankDirectory {
+    fn build<W: ArrayView<u64>>(words: &W, size_in_bits: usize) -> RankDirectory {
         let mut lb: Vec<u32> = vec![0; (size_in_bits + 511) / 512];
         let mut sb: Vec<u16> = vec![0; lb.len() * 8];
         let mut sum: u32 = 0;
         let mut sum_in_lb: u32 = 0;
         for i in 0..sb.len() {
-            let bit_count = if i < words.len() { u64::count_ones(words[i]) } else { 0 };
+            let bit_count = if i < words.len() {
+                u64::count_ones(words.at(i))
+            } else {
+                0
+            };
             sb[i] = sum_in_lb as u16;
             sum_in_lb = sum_in_lb + bit_count;
             if (i & 7) == 7 {
@@ -51,18 +61,36 @@ impl BitVector {
                 sum_in_lb = 0;
             }
         }
+        RankDirectory { lb, sb }
+    }
+}
+
+impl<W: ArrayView<u64>> BitVector<W> {
+    pub fn new(words: W, size_in_bits: usize) -> BitVector<W> {
+        assert!(
+            (size_in_bits + 63) / 64 == words.len(),
+            "Word vector length does not match the size in bits. Expected {}, got {}.",
+            (size_in_bits + 63) / 64,
+            words.len()
+        );
         BitVector {
             words: words,
             size_in_bits: size_in_bits,
-            lb: lb,
-            sb: sb,
+            directory: OnceLock::new(),
         }
     }
 
+    fn directory(&self) -> &RankDirectory {
+        self.directory
+            .get_or_init(|| RankDirectory::build(&self.words, self.size_in_bits))
+    }
+
     pub fn rank(&self, pos: usize, b: bool) -> usize {
         assert!(pos <= self.size_in_bits, "pos is out of bounds for rank");
-        let mut count1 = self.sb[(pos / 64) as usize] as usize + self.lb[(pos / 512) as usize] as usize;
-        let word = self.words[(pos / 64) as usize];
+        let directory = self.directory();
+        let mut count1 = directory.sb[(pos / 64) as usize] as usize + directory.lb[(pos / 512) as usize] as usize;
+        // posが末尾かつ64の倍数のときは、対応するワードが存在しない
+        let word = if pos & 63 == 0 { 0 } else { self.words.at(pos / 64) };
         let shift_size = 64 - (pos & 63);
         let mask = if shift_size == 64 {
             0
@@ -75,94 +103,39 @@ impl BitVector {
 
     pub fn select(&self, count: usize, b: bool) -> usize {
         assert!(count > 0, "select() requires a 1-indexed count, but got 0.");
-        let lb_index = self.lower_bound_binary_search_lb(count as u32, b) - 1;
+        let directory = self.directory();
+        let lb_index = Self::lower_bound_binary_search_lb(directory, count as u32, b) - 1;
         let count_in_lb: usize = if b {
-            count - self.lb[lb_index as usize] as usize
+            count - directory.lb[lb_index as usize] as usize
         } else {
-            count - (512 * lb_index - self.lb[lb_index as usize] as usize) as usize
+            count - (512 * lb_index - directory.lb[lb_index as usize] as usize) as usize
         };
-        let sb_index =
-            self.lower_bound_binary_search_sb(count_in_lb as u16, lb_index as usize * 8, lb_index as usize * 8 + 8, b)
-                - 1;
+        let sb_index = Self::lower_bound_binary_search_sb(
+            directory,
+            count_in_lb as u16,
+            lb_index as usize * 8,
+            lb_index as usize * 8 + 8,
+            b,
+        ) - 1;
         let count_in_sb = if b {
-            count_in_lb - self.sb[sb_index] as usize
+            count_in_lb - directory.sb[sb_index] as usize
         } else {
-            count_in_lb - (64 * (sb_index % 8) - self.sb[sb_index as usize] as usize)
+            count_in_lb - (64 * (sb_index % 8) - directory.sb[sb_index as usize] as usize)
         };
-        let mut word = self.words[sb_index];
+        let mut word = self.words.at(sb_index);
         if !b {
             word = !word;
         }
-        sb_index * 64 + Self::select_in_word(word, count_in_sb)
-    }
-
-    #[inline]
-    fn select_in_word(word: u64, count: usize) -> usize {
-        #[cfg(target_arch = "x86_64")]
-        {
-            if is_x86_feature_detected!("bmi2") {
-                return unsafe { Self::select_in_word_pdep(word, count) };
-            }
-        }
-
-        Self::select_in_word_fallback(word, count)
-    }
-
-    #[cfg(target_arch = "x86_64")]
-    #[target_feature(enable = "bmi2")]
-    #[inline]
-    unsafe fn select_in_word_pdep(word: u64, count: usize) -> usize {
-        let k_th_bit = 1_u64 << (count - 1);
-        let isolated_bit = _pdep_u64(k_th_bit, word);
-        isolated_bit.trailing_zeros() as usize
-    }
-
-    #[inline]
-    fn select_in_word_fallback(word: u64, count: usize) -> usize {
-        // count is 1-indexed.
-        assert!(count > 0, "count must be greater than 0 for select_in_word");
-
-        // Ensure the word has enough set bits.
-        assert!(
-            word.count_ones() as usize >= count,
-            "word (popcount: {}) has fewer than the required {} bits",
-            word.count_ones(),
-            count
-        );
-
-        let k0 = (count - 1) as u64;
-        let mut b = word;
-        b = b - ((b >> 1) & 0x5555555555555555);
-        b = (b & 0x3333333333333333) + ((b >> 2) & 0x3333333333333333);
-        b = (b + (b >> 4)) & 0x0F0F0F0F0F0F0F0F;
-
-        let mut ps = b;
-        ps += ps << 8;
-        ps += ps << 16;
-        ps += ps << 32;
-
-        let k_rep = k0 * 0x0101010101010101;
-        let high = 0x8080808080808080u64;
-        // Each byte in ps is 0..64 and k0 is 0..63, so the subtract is borrow-free per byte.
-        let le_mask = ((k_rep | high).wrapping_sub(ps)) & high;
-        let gt_mask = (!le_mask) & high;
-        let byte_idx = (gt_mask.trailing_zeros() >> 3) as u32;
-
-        let prev_ps = ps << 8;
-        let prev = ((prev_ps >> (byte_idx * 8)) & 0xFF) as u32;
-        let k_in = (k0 as u32) - prev;
-        let byte = ((word >> (byte_idx * 8)) & 0xFF) as u8;
-        let pos_in_byte = SELECT8[byte as usize][k_in as usize];
-        (byte_idx as usize) * 8 + (pos_in_byte as usize)
+        sb_index * 64 + BitVector::select_in_word(word, count_in_sb)
     }
 
-    fn lower_bound_binary_search_lb(&self, key: u32, b: bool) -> usize {
-        let mut high = self.lb.len() as isize;
+    fn lower_bound_binary_search_lb(directory: &RankDirectory, key: u32, b: bool) -> usize {
+        let mut high = directory.lb.len() as isize;
         let mut low: isize = -1;
         if b {
             while high - low > 1 {
                 let mid = (high + low) / 2;
-                if self.lb[mid as usize] < key {
+                if directory.lb[mid as usize] < key {
                     low = mid;
                 } else {
                     high = mid;
@@ -171,7 +144,7 @@ impl BitVector {
         } else {
             while high - low > 1 {
                 let mid = (high + low) / 2;
-                if ((mid << 9) as u32) - self.lb[mid as usize] < key {
+                if ((mid << 9) as u32) - directory.lb[mid as usize] < key {
                     low = mid
                 } else {
                     high = mid
@@ -181,13 +154,19 @@ impl BitVector {
         return high as usize;
     }
 
-    fn lower_bound_binary_search_sb(&self, key: u16, from_index: usize, to_index: usize, b: bool) -> usize {
+    fn lower_bound_binary_search_sb(
+        directory: &RankDirectory,
+        key: u16,
+        from_index: usize,
+        to_index: usize,
+        b: bool,
+    ) -> usize {
         let mut high = to_index as isize;
         let mut low = from_index as isize - 1;
         if b {
             while high - low > 1 {
                 let mid = (high + low) / 2;
-                if self.sb[mid as usize] < key {
+                if directory.sb[mid as usize] < key {
                     low = mid;
                 } else {
                     high = mid;
@@ -196,7 +175,7 @@ impl BitVector {
         } else {
             while high - low > 1 {
                 let mid = (high + low) >> 1;
-                if (((mid & 7) << 6) as u16) - self.sb[mid as usize] < key {
+                if (((mid & 7) << 6) as u16) - directory.sb[mid as usize] < key {
                     low = mid;
                 } else {
                     high = mid;
@@ -211,7 +190,7 @@ impl BitVector {
         if u >= self.words.len() {
             return from_index;
         }
-        let mut word = !self.words[u] & (0xFFFFFFFFFFFFFFFFu64 << (from_index & 63));
+        let mut word = !self.words.at(u) & (0xFFFFFFFFFFFFFFFFu64 << (from_index & 63));
         loop {
             if word != 0 {
                 return (u * 64) + (word.trailing_zeros() as usize);
@@ -220,12 +199,12 @@ impl BitVector {
             if u == self.words.len() {
                 return 64 * self.words.len();
             }
-            word = !self.words[u];
+            word = !self.words.at(u);
         }
     }
 
     pub fn size(&self) -> usize {
-        return self.size_in_bits as usize;
+        self.size_in_bits
     }
 
     pub fn get(&self, pos: usize) -> bool {
@@ -235,7 +214,78 @@ impl BitVector {
             pos,
             self.size_in_bits
         );
-        return ((self.words[(pos >> 6) as usize] >> (pos & 63)) & 1) == 1;
+        ((self.words.at(pos >> 6) >> (pos & 63)) & 1) == 1
+    }
+
+    /// ワードを`Vec`にコピーした、所有権を持つビットベクトルに変換する
+    pub fn into_owned(self) -> BitVector {
+        BitVector {
+            words: self.words.to_vec(),
+            size_in_bits: self.size_in_bits,
+            directory: self.directory,
+        }
+    }
+}
+
+impl BitVector {
+    #[inline]
+    fn select_in_word(word: u64, count: usize) -> usize {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("bmi2") {
+                return unsafe { Self::select_in_word_pdep(word, count) };
+            }
+        }
+
+        Self::select_in_word_fallback(word, count)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "bmi2")]
+    #[inline]
+    unsafe fn select_in_word_pdep(word: u64, count: usize) -> usize {
+        let k_th_bit = 1_u64 << (count - 1);
+        let isolated_bit = _pdep_u64(k_th_bit, word);
+        isolated_bit.trailing_zeros() as usize
+    }
+
+    #[inline]
+    fn select_in_word_fallback(word: u64, count: usize) -> usize {
+        // count is 1-indexed.
+        assert!(count > 0, "count must be greater than 0 for select_in_word");
+
+        // Ensure the word has enough set bits.
+        assert!(
+            word.count_ones() as usize >= count,
+            "word (popcount: {}) has fewer than the required {} bits",
+            word.count_ones(),
+            count
+        );
+
+        let k0 = (count - 1) as u64;
+        let mut b = word;
+        b = b - ((b >> 1) & 0x5555555555555555);
+        b = (b & 0x3333333333333333) + ((b >> 2) & 0x3333333333333333);
+        b = (b + (b >> 4)) & 0x0F0F0F0F0F0F0F0F;
+
+        let mut ps = b;
+        ps += ps << 8;
+        ps += ps << 16;
+        ps += ps << 32;
+
+        let k_rep = k0 * 0x0101010101010101;
+        let high = 0x8080808080808080u64;
+        // Each byte in ps is 0..64 and k0 is 0..63, so the subtract is borrow-free per byte.
+        let le_mask = ((k_rep | high).wrapping_sub(ps)) & high;
+        let gt_mask = (!le_mask) & high;
+        let byte_idx = gt_mask.trailing_zeros() >> 3;
+
+        let prev_ps = ps << 8;
+        let prev = ((prev_ps >> (byte_idx * 8)) & 0xFF) as u32;
+        let k_in = (k0 as u32) - prev;
+        let byte = ((word >> (byte_idx * 8)) & 0xFF) as u8;
+        let pos_in_byte = SELECT8[byte as usize][k_in as usize];
+        (byte_idx as usize) * 8 + (pos_in_byte as usize)
     }
 
     pub fn words(&self) -> &[u64] {
@@ -290,12 +340,20 @@ mod tests {
         }
         let words = bits_to_words(&bits);
         let bitvector = BitVector::new(words, SIZE);
-        for i in 0..SIZE {
+        for i in 0..=SIZE {
             assert_eq!(rank(&bits, i, true), bitvector.rank(i, true) as usize);
             assert_eq!(rank(&bits, i, false), bitvector.rank(i, false) as usize);
         }
     }
 
+    #[test]
+    fn test_rank_at_end_of_full_word() {
+        let bitvector = BitVector::new(vec![0xFF, !0], 128);
+        assert_eq!(bitvector.rank(64, true), 8);
+        assert_eq!(bitvector.rank(128, true), 72);
+        assert_eq!(bitvector.rank(128, false), 56);
+    }
+
     #[test]
     fn test_select() {
         fn select(bits: &Vec<bool>, count: usize, b: bool) -> Result<usize, &str> {
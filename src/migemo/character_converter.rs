@@ -42,6 +42,16 @@ pub fn hira2kata(source: &String) -> String {
     return sb;
 }
 
+pub fn kata2hira(source: &str) -> String {
+    source
+        .chars()
+        .map(|c| match c {
+            'ァ'..='ヴ' => std::char::from_u32((c as u32) - ('ァ' as u32) + ('ぁ' as u32)).unwrap(),
+            _ => c,
+        })
+        .collect()
+}
+
 const HAN2ZEN: [(char, char); 157] = [
     ('!', '！'),
     ('"', '”'),
@@ -1,59 +1,341 @@
+use super::array_view::{ArrayView, BigEndianArray};
 use super::bit_list::BitList;
 use super::bit_vector::BitVector;
+use super::dictionary_header::{DictionaryHeader, FLAG_WEIGHTS, FORMAT_VERSION, HEADER_SIZE, KNOWN_FLAGS, crc32};
 use super::louds_trie::LoudsTrie;
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashSet;
+use std::fmt;
 use std::io::Cursor;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::OnceLock;
 
+/// 辞書ファイルを構成するセクション
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DictionarySection {
+    Header,
+    KeyTrie,
+    ValueTrie,
+    MappingBitVector,
+    Mapping,
+    Weights,
+}
+
+impl fmt::Display for DictionarySection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DictionarySection::Header => "header",
+            DictionarySection::KeyTrie => "key trie",
+            DictionarySection::ValueTrie => "value trie",
+            DictionarySection::MappingBitVector => "mapping bit vector",
+            DictionarySection::Mapping => "mapping",
+            DictionarySection::Weights => "weights",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 辞書ファイルの読み込みに失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DictionaryError {
+    /// セクションの途中でファイルが終わっている
+    UnexpectedEof { section: DictionarySection, offset: usize },
+    /// セクションのサイズが、他のセクションから求まるサイズと一致しない
+    SizeMismatch {
+        section: DictionarySection,
+        offset: usize,
+        expected: usize,
+        actual: usize,
+    },
+    /// LOUDSのビット列が木構造になっていない
+    MalformedTrie { section: DictionarySection, offset: usize },
+    /// マッピングが値トライに存在しないノードを指している
+    InvalidMapping { offset: usize, value: u32 },
+    /// ヘッダーのバージョンに対応していない
+    UnsupportedVersion { version: u16 },
+    /// ヘッダーに解釈できない機能フラグが立っている
+    UnsupportedFlags { flags: u16 },
+    /// ペイロードのCRC-32がヘッダーの値と一致しない
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for DictionaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DictionaryError::UnexpectedEof { section, offset } => {
+                write!(f, "unexpected end of file in {} at byte {}", section, offset)
+            }
+            DictionaryError::SizeMismatch {
+                section,
+                offset,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "size mismatch in {} at byte {}: expected={}, actual={}",
+                section, offset, expected, actual
+            ),
+            DictionaryError::MalformedTrie { section, offset } => {
+                write!(f, "malformed {} at byte {}", section, offset)
+            }
+            DictionaryError::InvalidMapping { offset, value } => {
+                write!(f, "invalid mapping value {} at byte {}", value, offset)
+            }
+            DictionaryError::UnsupportedVersion { version } => {
+                write!(f, "unsupported format version {}", version)
+            }
+            DictionaryError::UnsupportedFlags { flags } => {
+                write!(f, "unsupported feature flags 0x{:04x}", flags)
+            }
+            DictionaryError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected=0x{:08x}, actual=0x{:08x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DictionaryError {}
+
+/// キーのトライのエッジ
+///
+/// 辞書ファイルでは1文字を1バイトに詰めて格納しているので、参照するたびにUTF-16へ戻す。
+#[derive(Debug, Clone, Copy)]
+pub struct CompactHiraganaArray<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> CompactHiraganaArray<'a> {
+    pub fn new(bytes: &'a [u8]) -> CompactHiraganaArray<'a> {
+        CompactHiraganaArray { bytes }
+    }
+}
+
+impl ArrayView<u16> for CompactHiraganaArray<'_> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    #[inline]
+    fn at(&self, index: usize) -> u16 {
+        CompactDictionary::decode(self.bytes[index])
+    }
+}
+
+/// 辞書の各セクションを、どの形式の配列で保持するか
+pub trait DictionaryStorage {
+    type Words: ArrayView<u64> + fmt::Debug;
+    type KeyEdges: ArrayView<u16> + fmt::Debug;
+    type ValueEdges: ArrayView<u16> + fmt::Debug;
+    type Mapping: ArrayView<u32> + fmt::Debug;
+}
+
+/// 読み込み時に、各セクションを`Vec`へ展開して保持する
+#[derive(Debug)]
+pub struct Owned;
+
+impl DictionaryStorage for Owned {
+    type Words = Vec<u64>;
+    type KeyEdges = Vec<u16>;
+    type ValueEdges = Vec<u16>;
+    type Mapping = Vec<u32>;
+}
+
+/// 辞書ファイルのバイト列をコピーせずに参照し、必要になった箇所だけをその場で読む
 #[derive(Debug)]
-pub struct CompactDictionary {
-    key_trie: LoudsTrie,
-    value_trie: LoudsTrie,
-    mapping_bit_vector: BitVector,
-    mapping: Vec<u32>,
-    has_mapping_bit_list: BitList,
+pub struct Borrowed<'a>(PhantomData<&'a [u8]>);
+
+impl<'a> DictionaryStorage for Borrowed<'a> {
+    type Words = BigEndianArray<'a, u64>;
+    type KeyEdges = CompactHiraganaArray<'a>;
+    type ValueEdges = BigEndianArray<'a, u16>;
+    type Mapping = BigEndianArray<'a, u32>;
+}
+
+// 辞書ファイルを読み込むときに、どこまで検査するか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Validation {
+    // セクションの境界だけを検査する
+    Bounds,
+    // ヘッダーのCRC-32も検証する。ヘッダーのない辞書ファイルは、CRC-32の代わりに構造を検査する
+    Checksum,
+    // CRC-32に加えて、トライの構造とマッピングの値もすべて検査する
+    Full,
 }
 
-pub struct SearchIter<'a> {
-    dict: &'a CompactDictionary,
-    size: usize,
+/// 辞書ファイルを先頭から順に読み進める
+struct SectionReader<'a> {
+    buffer: &'a [u8],
     offset: usize,
-    value_start_pos: usize,
-    i: usize,
 }
 
-impl<'a> Iterator for SearchIter<'a> {
+impl<'a> SectionReader<'a> {
+    /// ヘッダーがあれば検証し、ペイロードの先頭から読み進めるリーダーを作る。
+    /// ヘッダーのない古い辞書ファイルは、先頭から読み進める。
+    fn open(
+        buffer: &'a [u8],
+        verify_checksum: bool,
+    ) -> Result<(Option<DictionaryHeader>, SectionReader<'a>), DictionaryError> {
+        if !DictionaryHeader::is_present(buffer) {
+            return Ok((None, SectionReader { buffer, offset: 0 }));
+        }
+        let section = DictionarySection::Header;
+        let header = match DictionaryHeader::parse(buffer) {
+            Some(header) => header,
+            None => return Err(DictionaryError::UnexpectedEof { section, offset: 0 }),
+        };
+        if header.version == 0 || header.version > FORMAT_VERSION {
+            return Err(DictionaryError::UnsupportedVersion {
+                version: header.version,
+            });
+        }
+        let unknown_flags = header.flags & !KNOWN_FLAGS;
+        if unknown_flags != 0 {
+            return Err(DictionaryError::UnsupportedFlags { flags: unknown_flags });
+        }
+        let payload = &buffer[HEADER_SIZE..];
+        if payload.len() != header.payload_length as usize {
+            return Err(DictionaryError::SizeMismatch {
+                section,
+                offset: 12,
+                expected: header.payload_length as usize,
+                actual: payload.len(),
+            });
+        }
+        if verify_checksum {
+            let checksum = crc32(payload);
+            if checksum != header.checksum {
+                return Err(DictionaryError::ChecksumMismatch {
+                    expected: header.checksum,
+                    actual: checksum,
+                });
+            }
+        }
+        Ok((
+            Some(header),
+            SectionReader {
+                buffer,
+                offset: HEADER_SIZE,
+            },
+        ))
+    }
+
+    fn take(
+        &mut self,
+        count: usize,
+        element_size: usize,
+        section: DictionarySection,
+    ) -> Result<&'a [u8], DictionaryError> {
+        let remaining = self.buffer.len().saturating_sub(self.offset);
+        match count.checked_mul(element_size) {
+            Some(len) if len <= remaining => {
+                let bytes = &self.buffer[self.offset..self.offset + len];
+                self.offset += len;
+                Ok(bytes)
+            }
+            _ => Err(DictionaryError::UnexpectedEof {
+                section,
+                offset: self.offset,
+            }),
+        }
+    }
+
+    fn read_u32(&mut self, section: DictionarySection) -> Result<u32, DictionaryError> {
+        let bytes = self.take(1, 4, section)?;
+        Ok(BigEndian::read_u32(bytes))
+    }
+
+    fn read_bit_vector(
+        &mut self,
+        section: DictionarySection,
+    ) -> Result<BitVector<BigEndianArray<'a, u64>>, DictionaryError> {
+        let size_in_bits = self.read_u32(section)? as usize;
+        let words = self.take(size_in_bits.div_ceil(64), 8, section)?;
+        Ok(BitVector::new(BigEndianArray::new(words), size_in_bits))
+    }
+
+    fn read_trie<E: ArrayView<u16>>(
+        &mut self,
+        section: DictionarySection,
+        edge_size: usize,
+        edges: fn(&'a [u8]) -> E,
+        check_structure: bool,
+    ) -> Result<LoudsTrie<BigEndianArray<'a, u64>, E>, DictionaryError> {
+        let edges_offset = self.offset;
+        let edge_count = self.read_u32(section)? as usize;
+        let edges = edges(self.take(edge_count, edge_size, section)?);
+        // 先頭の2つはダミーのエッジで、ルートノードが必ず存在する
+        if edge_count < 2 {
+            return Err(DictionaryError::SizeMismatch {
+                section,
+                offset: edges_offset,
+                expected: 2,
+                actual: edge_count,
+            });
+        }
+        let bit_vector_offset = self.offset;
+        let bit_vector = self.read_bit_vector(section)?;
+        let louds_trie = LoudsTrie { bit_vector, edges };
+        if check_structure && !louds_trie.is_well_formed() {
+            return Err(DictionaryError::MalformedTrie {
+                section,
+                offset: bit_vector_offset,
+            });
+        }
+        Ok(louds_trie)
+    }
+}
+
+#[derive(Debug)]
+pub struct CompactDictionary<S: DictionaryStorage = Owned> {
+    header: Option<DictionaryHeader>,
+    key_trie: LoudsTrie<S::Words, S::KeyEdges>,
+    value_trie: LoudsTrie<S::Words, S::ValueEdges>,
+    mapping_bit_vector: BitVector<S::Words>,
+    mapping: S::Mapping,
+    // マッピングと同じ順に並べた単語の重み。重みを持たない辞書では`None`
+    weights: Option<S::Mapping>,
+    // キーのノードごとに単語を持つかどうか。最初に単語を引くときに作る
+    has_mapping_bit_list: OnceLock<BitList>,
+}
+
+/// 辞書ファイルのバイト列を借用する辞書
+pub type CompactDictionaryRef<'a> = CompactDictionary<Borrowed<'a>>;
+
+pub struct SearchIter<'a, S: DictionaryStorage = Owned> {
+    dict: &'a CompactDictionary<S>,
+    // マッピング上の、読みの単語の範囲
+    values: Range<usize>,
+}
+
+impl<'a, S: DictionaryStorage> Iterator for SearchIter<'a, S> {
     type Item = Vec<u16>;
     fn next(&mut self) -> Option<Vec<u16>> {
-        if self.i < self.size {
-            let _i = self.i;
-            self.i = self.i + 1;
-            return Some(
-                self.dict
-                    .value_trie
-                    .get_key(self.dict.mapping[self.value_start_pos - (self.offset as usize) + _i] as usize),
-            );
-        } else {
-            return None;
-        }
+        let i = self.values.next()?;
+        Some(self.dict.value_trie.get_key(self.dict.mapping.at(i) as usize))
     }
 }
 
-pub struct PredictiveSearchIter<'a> {
-    dict: &'a CompactDictionary,
+pub struct PredictiveSearchIter<'a, S: DictionaryStorage = Owned> {
+    dict: &'a CompactDictionary<S>,
     // key_trieから前方一致で得られたノードIDのイテレータ
     key_node_indices: std::vec::IntoIter<usize>,
-    // 現在のキーノードが持つ、値IDのリスト
-    current_values: std::vec::IntoIter<u32>,
+    // 現在のキーノードが持つ、値IDのマッピング上の範囲
+    current_values: Range<usize>,
     // 値を取得する際に再利用するバッファ
     key_buffer: Vec<u16>,
 }
 
-impl<'a> Iterator for PredictiveSearchIter<'a> {
+impl<'a, S: DictionaryStorage> Iterator for PredictiveSearchIter<'a, S> {
     type Item = Vec<u16>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            if let Some(mapping_index) = self.current_values.next() {
+            if let Some(i) = self.current_values.next() {
+                let mapping_index = self.dict.mapping.at(i);
                 self.key_buffer.clear();
                 self.dict
                     .value_trie
@@ -62,21 +344,9 @@ impl<'a> Iterator for PredictiveSearchIter<'a> {
             }
 
             let dict = self.dict;
-            let next_values = self.key_node_indices.find_map(|node_index| {
-                if node_index < dict.has_mapping_bit_list.len() && dict.has_mapping_bit_list.get(node_index) {
-                    let value_start_pos = dict.mapping_bit_vector.select(node_index, false);
-                    let value_end_pos = dict.mapping_bit_vector.next_clear_bit(value_start_pos + 1);
-                    let size = value_end_pos - value_start_pos - 1;
-
-                    if size > 0 {
-                        let offset = dict.mapping_bit_vector.rank(value_start_pos, false);
-                        let start = value_start_pos - offset;
-                        let end = start + size;
-                        return Some(dict.mapping[start..end].to_vec().into_iter());
-                    }
-                }
-                None
-            });
+            let next_values = self
+                .key_node_indices
+                .find_map(|node_index| dict.value_range(node_index));
 
             if let Some(values) = next_values {
                 self.current_values = values;
@@ -87,66 +357,83 @@ impl<'a> Iterator for PredictiveSearchIter<'a> {
     }
 }
 
+pub struct EntriesIter<'a, S: DictionaryStorage = Owned> {
+    dict: &'a CompactDictionary<S>,
+    // 次に調べるキーのノードID
+    node_index: usize,
+}
+
+impl<'a, S: DictionaryStorage> Iterator for EntriesIter<'a, S> {
+    type Item = (String, Vec<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let num_of_nodes = self.dict.key_trie.edges.len() - 1;
+        while self.node_index <= num_of_nodes {
+            let node_index = self.node_index;
+            self.node_index += 1;
+            if let Some(values) = self.dict.value_range(node_index) {
+                let key = String::from_utf16_lossy(&self.dict.key_trie.get_key(node_index));
+                let words = values
+                    .map(|i| {
+                        let value = self.dict.value_trie.get_key(self.dict.mapping.at(i) as usize);
+                        String::from_utf16_lossy(&value)
+                    })
+                    .collect();
+                return Some((key, words));
+            }
+        }
+        None
+    }
+}
+
+/// 読みを編集距離で近似して引いた単語
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub word: Vec<u16>,
+    /// 単語の重み。重みを持たない辞書では0になる。
+    pub weight: u32,
+    /// 検索した読みに近かった辞書の読み。前方一致で引いた場合は、単語の読みの先頭部分になる。
+    pub key: Vec<u16>,
+    /// 検索した読みと`key`との編集距離
+    pub distance: usize,
+}
+
 impl CompactDictionary {
-    pub fn new(buffer: &Vec<u8>) -> CompactDictionary {
-        let mut cursor = Cursor::new(buffer);
-        let key_trie = CompactDictionary::read_trie(&mut cursor, true);
-        let value_trie = CompactDictionary::read_trie(&mut cursor, false);
-        let mapping_bit_vector_size = cursor.read_u32::<BigEndian>().unwrap() as usize;
-        let mut mapping_bit_vector_words = vec![0; (mapping_bit_vector_size + 63) / 64];
-        for i in 0..mapping_bit_vector_words.len() {
-            mapping_bit_vector_words[i] = cursor.read_u64::<BigEndian>().unwrap();
-        }
-        let mapping_bit_vector = BitVector::new(mapping_bit_vector_words, mapping_bit_vector_size);
-        let mapping_size = cursor.read_u32::<BigEndian>().unwrap();
-        let mut mapping: Vec<u32> = vec![0; mapping_size as usize];
-        for i in 0..mapping_size {
-            mapping[i as usize] = cursor.read_u32::<BigEndian>().unwrap();
-        }
-        let has_mapping_bit_list = CompactDictionary::create_mapping_bit_list(&mapping_bit_vector);
-        return CompactDictionary {
-            key_trie: key_trie,
-            value_trie: value_trie,
-            mapping_bit_vector: mapping_bit_vector,
-            mapping: mapping,
-            has_mapping_bit_list: has_mapping_bit_list,
-        };
+    pub fn new(buffer: &[u8]) -> CompactDictionary {
+        match CompactDictionary::try_from_bytes(buffer) {
+            Ok(dict) => dict,
+            Err(e) => panic!("Fail to load dict: {}", e),
+        }
     }
 
-    pub fn read_trie(cursor: &mut Cursor<&Vec<u8>>, compact_hiragana: bool) -> LoudsTrie {
-        let key_trie_edge_size = cursor.read_u32::<BigEndian>().unwrap();
-        let mut key_trie_edges = vec![0; key_trie_edge_size as usize];
-        for i in 0..key_trie_edge_size {
-            let c: u16 = if compact_hiragana {
-                CompactDictionary::decode(cursor.read_u8().unwrap())
-            } else {
-                cursor.read_u16::<BigEndian>().unwrap()
-            };
-            key_trie_edges[i as usize] = c;
-        }
-        let key_trie_bit_vector_size = cursor.read_u32::<BigEndian>().unwrap();
-        let mut key_trie_bit_vector_words: Vec<u64> = vec![0; (key_trie_bit_vector_size as usize + 63) / 64];
-        for i in 0..key_trie_bit_vector_words.len() {
-            key_trie_bit_vector_words[i] = cursor.read_u64::<BigEndian>().unwrap();
-        }
-        let bit_vector = BitVector::new(key_trie_bit_vector_words, key_trie_bit_vector_size as usize);
-        let louds_trie = LoudsTrie {
-            bit_vector: bit_vector,
-            edges: key_trie_edges,
-        };
-        return louds_trie;
+    /// 辞書ファイルを読み込む。壊れたファイルに対してはパニックせずにエラーを返す。
+    ///
+    /// 各セクションを展開するのでファイル全体を読むことになり、構造の検査もすべて行う。
+    pub fn try_from_bytes(buffer: &[u8]) -> Result<CompactDictionary, DictionaryError> {
+        CompactDictionary::try_borrow_from_bytes(buffer).map(CompactDictionaryRef::into_owned)
     }
 
-    fn create_mapping_bit_list(bit_vector: &BitVector) -> BitList {
-        let num_of_nodes = bit_vector.rank(bit_vector.size(), false);
-        let mut bit_list = BitList::new_with_size(num_of_nodes + 1);
-        let mut bit_position = 0;
-        for node in 1..=num_of_nodes {
-            let has_mapping = bit_vector.get(bit_position + 1);
-            bit_list.set(node, has_mapping);
-            bit_position = bit_vector.next_clear_bit(bit_position + 1)
+    /// カーソルの位置からトライを1つ読み込む。壊れたファイルに対してはパニックする。
+    #[deprecated(note = "壊れたファイルでパニックする。エラーを返す`CompactDictionary::try_from_bytes`を使う")]
+    pub fn read_trie(cursor: &mut Cursor<&Vec<u8>>, compact_hiragana: bool) -> LoudsTrie {
+        let mut reader = SectionReader {
+            buffer: cursor.get_ref(),
+            offset: cursor.position() as usize,
+        };
+        let trie = if compact_hiragana {
+            reader
+                .read_trie(DictionarySection::KeyTrie, 1, CompactHiraganaArray::new, true)
+                .map(LoudsTrie::into_owned)
+        } else {
+            reader
+                .read_trie(DictionarySection::ValueTrie, 2, BigEndianArray::new, true)
+                .map(LoudsTrie::into_owned)
+        };
+        cursor.set_position(reader.offset as u64);
+        match trie {
+            Ok(trie) => trie,
+            Err(e) => panic!("Fail to read trie: {}", e),
         }
-        return bit_list;
     }
 
     fn decode(b: u8) -> u16 {
@@ -166,35 +453,205 @@ impl CompactDictionary {
             _ => 0,
         }
     }
+}
 
-    pub fn search(&self, key: &Vec<u16>) -> SearchIter<'_> {
-        let key_index = self.key_trie.get(key);
-        if key_index.is_some() {
-            let key_index = key_index.unwrap();
-            let value_start_pos = self.mapping_bit_vector.select(key_index as usize, false);
-            let value_end_pos = self.mapping_bit_vector.next_clear_bit(value_start_pos + 1);
-            let size = value_end_pos - value_start_pos - 1;
-            if size > 0 {
-                let offset = self.mapping_bit_vector.rank(value_start_pos, false);
-                return SearchIter {
-                    dict: self,
-                    size: size as usize,
-                    offset: offset as usize,
-                    value_start_pos: value_start_pos,
-                    i: 0,
-                };
+impl<'a> CompactDictionary<Borrowed<'a>> {
+    /// 辞書ファイルのバイト列をコピーせずに参照する辞書を作る。
+    ///
+    /// ヘッダーのCRC-32に加えて、トライの構造とマッピングの値もすべて検査するので、
+    /// 読み込めた辞書は検索中にパニックしない。読み込みにはファイルの大きさに比例した時間がかかる。
+    pub fn try_borrow_from_bytes(buffer: &'a [u8]) -> Result<CompactDictionaryRef<'a>, DictionaryError> {
+        Self::borrow_with(buffer, Validation::Full)
+    }
+
+    /// ヘッダーのバージョンと機能フラグ、ペイロードのCRC-32だけを検証して、辞書を作る。
+    ///
+    /// トライの構造とマッピングの値の検査を省くので、mmapしたファイルならすぐに使い始められる。
+    /// CRC-32は誰でも計算できるため、細工したファイルや誤ったビルダーの出力は検出できず、検索中にパニックすることがある。
+    /// 自分で生成したファイルなど、内容を信頼できる場合にだけ使う。ヘッダーのない古い辞書ファイルは、構造をすべて検査する。
+    pub fn borrow_from_bytes_trusting_checksum(buffer: &'a [u8]) -> Result<CompactDictionaryRef<'a>, DictionaryError> {
+        Self::borrow_with(buffer, Validation::Checksum)
+    }
+
+    /// 各セクションの境界だけを検査して、辞書を作る。CRC-32も検証しないので、ファイルの大きさによらずすぐに終わる。
+    ///
+    /// 壊れたファイルを渡した場合、未定義動作にはならないが、検索中にパニックしたり、誤った単語を返したりする。
+    /// 自分で生成して検証済みのファイルなど、内容を信頼できる場合にだけ使う。
+    pub fn borrow_from_bytes_unchecked(buffer: &'a [u8]) -> Result<CompactDictionaryRef<'a>, DictionaryError> {
+        Self::borrow_with(buffer, Validation::Bounds)
+    }
+
+    fn borrow_with(buffer: &'a [u8], validation: Validation) -> Result<CompactDictionaryRef<'a>, DictionaryError> {
+        let (header, mut reader) = SectionReader::open(buffer, validation != Validation::Bounds)?;
+        let check_structure =
+            validation == Validation::Full || (validation == Validation::Checksum && header.is_none());
+        let key_trie = reader.read_trie(
+            DictionarySection::KeyTrie,
+            1,
+            CompactHiraganaArray::new,
+            check_structure,
+        )?;
+        let value_trie = reader.read_trie(DictionarySection::ValueTrie, 2, BigEndianArray::new, check_structure)?;
+
+        // マッピングのビットベクトルは、キーのノードごとに0を1つ、値ごとに1を1つ持つ
+        let section = DictionarySection::MappingBitVector;
+        let mapping_bit_vector_offset = reader.offset;
+        let mapping_bit_vector = reader.read_bit_vector(section)?;
+        let mapping_bit_vector_size = mapping_bit_vector.size();
+        if check_structure {
+            // 古いビルダーは最後のノードの0を出力しないため、ノード数より1つ少ない場合も受け入れる
+            let num_of_key_nodes = key_trie.edges.len() - 1;
+            let num_of_mapping_nodes = mapping_bit_vector.rank(mapping_bit_vector_size, false);
+            if num_of_mapping_nodes != num_of_key_nodes && num_of_mapping_nodes + 1 != num_of_key_nodes {
+                return Err(DictionaryError::SizeMismatch {
+                    section,
+                    offset: mapping_bit_vector_offset,
+                    expected: num_of_key_nodes,
+                    actual: num_of_mapping_nodes,
+                });
             }
         }
-        return SearchIter {
-            dict: self,
-            offset: 0,
-            size: 0,
-            i: 0,
-            value_start_pos: 0,
+
+        let section = DictionarySection::Mapping;
+        let mapping_offset = reader.offset;
+        let mapping_size = reader.read_u32(section)? as usize;
+        let mapping = BigEndianArray::<u32>::new(reader.take(mapping_size, 4, section)?);
+        if check_structure {
+            // 古いビルダーの出力では、ビットベクトルから参照されない値が末尾に残っていることがある
+            let num_of_mapping_values = mapping_bit_vector.rank(mapping_bit_vector_size, true);
+            if mapping_size < num_of_mapping_values {
+                return Err(DictionaryError::SizeMismatch {
+                    section,
+                    offset: mapping_offset,
+                    expected: num_of_mapping_values,
+                    actual: mapping_size,
+                });
+            }
+            for i in 0..num_of_mapping_values {
+                let value = mapping.at(i);
+                // 参照される値は、値トライのルート(1)より後ろのノードを指していなければならない
+                if value <= 1 || value as usize >= value_trie.edges.len() {
+                    return Err(DictionaryError::InvalidMapping {
+                        offset: mapping_offset + 4 + i * 4,
+                        value,
+                    });
+                }
+            }
+        }
+
+        // 重みはマッピングと同じ数だけ並ぶ
+        let weights = if header.is_some_and(|h| h.flags & FLAG_WEIGHTS != 0) {
+            let section = DictionarySection::Weights;
+            let weights_offset = reader.offset;
+            let weights_size = reader.read_u32(section)? as usize;
+            if weights_size != mapping_size {
+                return Err(DictionaryError::SizeMismatch {
+                    section,
+                    offset: weights_offset,
+                    expected: mapping_size,
+                    actual: weights_size,
+                });
+            }
+            Some(BigEndianArray::<u32>::new(reader.take(weights_size, 4, section)?))
+        } else {
+            None
         };
+        Ok(CompactDictionary {
+            header,
+            key_trie,
+            value_trie,
+            mapping_bit_vector,
+            mapping,
+            weights,
+            has_mapping_bit_list: OnceLock::new(),
+        })
+    }
+
+    /// 参照しているバイト列から、各セクションを`Vec`へ展開した辞書を作る
+    pub fn into_owned(self) -> CompactDictionary {
+        CompactDictionary {
+            header: self.header,
+            key_trie: self.key_trie.into_owned(),
+            value_trie: self.value_trie.into_owned(),
+            mapping_bit_vector: self.mapping_bit_vector.into_owned(),
+            mapping: self.mapping.to_vec(),
+            weights: self.weights.map(|weights| weights.to_vec()),
+            has_mapping_bit_list: self.has_mapping_bit_list,
+        }
     }
+}
 
-    pub fn predictive_search<'a>(&'a self, key: &[u16]) -> PredictiveSearchIter<'a> {
+impl<S: DictionaryStorage> CompactDictionary<S> {
+    /// 辞書ファイルのヘッダー。ヘッダーのない古い辞書ファイルでは`None`を返す。
+    pub fn header(&self) -> Option<DictionaryHeader> {
+        self.header
+    }
+
+    /// 単語に重み (出現頻度など) が付いているか
+    pub fn has_weights(&self) -> bool {
+        self.weights.is_some()
+    }
+
+    fn has_mapping_bit_list(&self) -> &BitList {
+        self.has_mapping_bit_list
+            .get_or_init(|| Self::create_mapping_bit_list(&self.mapping_bit_vector))
+    }
+
+    fn create_mapping_bit_list(bit_vector: &BitVector<S::Words>) -> BitList {
+        let num_of_nodes = bit_vector.rank(bit_vector.size(), false);
+        let mut bit_list = BitList::new_with_size(num_of_nodes + 1);
+        let mut bit_position = 0;
+        for node in 1..=num_of_nodes {
+            let has_mapping = bit_position + 1 < bit_vector.size() && bit_vector.get(bit_position + 1);
+            bit_list.set(node, has_mapping);
+            bit_position = bit_vector.next_clear_bit(bit_position + 1)
+        }
+        return bit_list;
+    }
+
+    /// キーのノードが持つ値の、マッピング上の範囲を返す。値を持たないノードでは`None`を返す。
+    fn value_range(&self, node_index: usize) -> Option<Range<usize>> {
+        let has_mapping_bit_list = self.has_mapping_bit_list();
+        if node_index >= has_mapping_bit_list.len() || !has_mapping_bit_list.get(node_index) {
+            return None;
+        }
+        let value_start_pos = self.mapping_bit_vector.select(node_index, false);
+        let value_end_pos = self.mapping_bit_vector.next_clear_bit(value_start_pos + 1);
+        let size = value_end_pos - value_start_pos - 1;
+        if size == 0 {
+            return None;
+        }
+        let offset = self.mapping_bit_vector.rank(value_start_pos, false);
+        let start = value_start_pos - offset;
+        Some(start..start + size)
+    }
+
+    // マッピング上の位置にある単語と、その重みを返す
+    fn weighted_value(&self, i: usize) -> (Vec<u16>, u32) {
+        let value = self.value_trie.get_key(self.mapping.at(i) as usize);
+        let weight = self.weights.as_ref().map_or(0, |weights| weights.at(i));
+        (value, weight)
+    }
+
+    /// 辞書に含まれるすべての読みと、その単語の一覧を返す。
+    /// 読みはキーのトライのノード順 (幅優先) に並ぶ。
+    pub fn entries(&self) -> EntriesIter<'_, S> {
+        EntriesIter {
+            dict: self,
+            node_index: 2,
+        }
+    }
+
+    pub fn search(&self, key: &[u16]) -> SearchIter<'_, S> {
+        let values = self
+            .key_trie
+            .get(key)
+            .and_then(|key_index| self.value_range(key_index))
+            .unwrap_or(0..0);
+        SearchIter { dict: self, values }
+    }
+
+    pub fn predictive_search<'a>(&'a self, key: &[u16]) -> PredictiveSearchIter<'a, S> {
         // TODO: ノードIDのリストを取得してからイテレータを生成しているため、半遅延評価であり、効率が悪い
         let key_node_indices_vec = if let Some(key_index) = self.key_trie.get(key) {
             if key_index > 1 {
@@ -209,18 +666,92 @@ impl CompactDictionary {
         PredictiveSearchIter {
             dict: self,
             key_node_indices: key_node_indices_vec.into_iter(),
-            current_values: Vec::new().into_iter(),
+            current_values: 0..0,
             key_buffer: Vec::with_capacity(16),
         }
     }
+
+    /// `search`と同じ単語を、重みとともに返す。重みを持たない辞書では、重みはすべて0になる。
+    pub fn search_weighted<'a>(&'a self, key: &[u16]) -> impl Iterator<Item = (Vec<u16>, u32)> + 'a {
+        let values = self
+            .key_trie
+            .get(key)
+            .and_then(|key_index| self.value_range(key_index))
+            .unwrap_or(0..0);
+        values.map(move |i| self.weighted_value(i))
+    }
+
+    /// `predictive_search`と同じ単語を、同じ順に重みとともに返す。重みを持たない辞書では、重みはすべて0になる。
+    pub fn predictive_search_weighted<'a>(&'a self, key: &[u16]) -> impl Iterator<Item = (Vec<u16>, u32)> + 'a {
+        let key_node_indices: Vec<usize> = match self.key_trie.get(key) {
+            Some(key_index) if key_index > 1 => self.key_trie.predictive_search(key_index).collect(),
+            _ => Vec::new(),
+        };
+        key_node_indices
+            .into_iter()
+            .filter_map(move |node_index| self.value_range(node_index))
+            .flatten()
+            .map(move |i| self.weighted_value(i))
+    }
+
+    /// 読みとの編集距離が`max_edits`以下の読みを持つ単語を、編集距離の小さい順に返す
+    ///
+    /// `predictive`が`true`の場合は、近い読みで前方一致検索した単語も返す。ただし、入力より短い読み
+    /// (「けんさ」に対する「けん」など) からは候補が増えすぎるので、その読みの単語だけを返す。
+    /// 入力が`max_edits`文字以下の場合は、どの短い読みとも近くなってしまうので何も返さない。
+    /// 単語は取り出すたびに引くので、必要な数だけ取り出せば残りの部分木はたどらない。
+    pub fn fuzzy_search_weighted<'a>(
+        &'a self,
+        key: &[u16],
+        max_edits: usize,
+        predictive: bool,
+    ) -> impl Iterator<Item = FuzzyMatch> + 'a {
+        let mut nodes = if key.len() > max_edits {
+            self.key_trie.fuzzy_search(key, max_edits)
+        } else {
+            Vec::new()
+        };
+        nodes.sort_by_key(|&(_, distance)| distance);
+        let key_len = key.len();
+        // 複数の近い読みから同じノードに届いた場合は、編集距離の小さいほうだけを使う
+        let mut seen = HashSet::new();
+        nodes
+            .into_iter()
+            .flat_map(move |(node, distance)| {
+                let reading = self.key_trie.get_key(node);
+                let key_node_indices: Box<dyn Iterator<Item = usize> + 'a> = if predictive && reading.len() >= key_len {
+                    Box::new(self.key_trie.predictive_search(node))
+                } else {
+                    Box::new(std::iter::once(node))
+                };
+                key_node_indices.map(move |node_index| (node_index, distance, reading.clone()))
+            })
+            .filter(move |&(node_index, _, _)| seen.insert(node_index))
+            .flat_map(move |(node_index, distance, reading)| {
+                self.value_range(node_index).unwrap_or(0..0).map(move |i| {
+                    let (word, weight) = self.weighted_value(i);
+                    FuzzyMatch {
+                        word,
+                        weight,
+                        key: reading.clone(),
+                        distance,
+                    }
+                })
+            })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::migemo::compact_dictionary_builder::{BuildOptions, build, build_with_options};
+    use crate::migemo::test_support::{build_dict, dict_entries};
+    use std::collections::HashMap;
     use std::fs::File;
     use std::io::Read;
 
+    const SMALL_ENTRIES: &[(&str, &[&str])] = &[("けんさ", &["検査"]), ("けんさく", &["検索", "研削"])];
+
     #[test]
     fn test_1() {
         let mut f = File::open("todofuken").expect("Fail to load dict file");
@@ -233,4 +764,394 @@ mod tests {
             println!("{}", String::from_utf16_lossy(&s));
         }
     }
+
+    fn build_small_dict() -> Vec<u8> {
+        build(dict_entries(SMALL_ENTRIES))
+    }
+
+    #[test]
+    fn try_from_bytes_accepts_valid_file() {
+        let buffer = build_small_dict();
+        let dict = CompactDictionary::try_from_bytes(&buffer).unwrap();
+        let word: Vec<u16> = "けんさく".encode_utf16().collect();
+        assert_eq!(dict.search(&word).count(), 2);
+    }
+
+    #[test]
+    fn fuzzy_search_tolerates_typos() {
+        let buffer = build_small_dict();
+        let dict = CompactDictionary::new(&buffer);
+        let search = |key: &str, predictive: bool| -> Vec<(String, String, usize)> {
+            let key: Vec<u16> = key.encode_utf16().collect();
+            dict.fuzzy_search_weighted(&key, 1, predictive)
+                .map(|m| {
+                    let word = String::from_utf16_lossy(&m.word);
+                    (word, String::from_utf16_lossy(&m.key), m.distance)
+                })
+                .collect()
+        };
+        assert_eq!(
+            search("けんすく", false),
+            vec![
+                ("検索".to_string(), "けんさく".to_string(), 1),
+                ("研削".to_string(), "けんさく".to_string(), 1)
+            ]
+        );
+        // 入力より短い「けんさ」からは前方一致で引かない
+        let found = search("けんさあ", true);
+        assert_eq!(found[0], ("検査".to_string(), "けんさ".to_string(), 1));
+        assert!(found.contains(&("検索".to_string(), "けんさく".to_string(), 1)));
+        assert_eq!(search("けす", false), Vec::new());
+        assert_eq!(search("かす", true), Vec::new());
+    }
+
+    #[test]
+    fn fuzzy_search_skips_short_keys() {
+        let dict = build_dict(&[
+            ("か", &["蚊"]),
+            ("さ", &["差"]),
+            ("さくら", &["桜"]),
+            ("かん", &["缶"]),
+            ("さん", &["三"]),
+            ("さんま", &["秋刀魚"]),
+        ]);
+        let search = |key: &str, max_edits: usize| -> Vec<String> {
+            let key: Vec<u16> = key.encode_utf16().collect();
+            dict.fuzzy_search_weighted(&key, max_edits, true)
+                .map(|m| String::from_utf16_lossy(&m.word))
+                .collect()
+        };
+        // 1文字の入力を1文字の誤りまで許すと、どの読みからでも前方一致で辞書全体を引いてしまう
+        assert_eq!(search("か", 1), Vec::<String>::new());
+        assert_eq!(search("かん", 2), Vec::<String>::new());
+        let found = search("かん", 1);
+        assert_eq!(found[0], "缶");
+        assert!(found.contains(&"秋刀魚".to_string()));
+        assert!(!found.contains(&"桜".to_string()));
+        // 必要な数だけ取り出せる
+        let key: Vec<u16> = "かん".encode_utf16().collect();
+        assert_eq!(dict.fuzzy_search_weighted(&key, 1, true).take(1).count(), 1);
+    }
+
+    #[test]
+    fn try_from_bytes_reports_truncated_file() {
+        let buffer = build_small_dict();
+        // 値トライのエッジの途中で切り詰める
+        let result = CompactDictionary::try_from_bytes(&buffer[..30]);
+        assert_eq!(
+            result.unwrap_err(),
+            DictionaryError::UnexpectedEof {
+                section: DictionarySection::ValueTrie,
+                offset: 26
+            }
+        );
+        // マッピングの途中で切り詰める
+        let result = CompactDictionary::try_from_bytes(&buffer[..buffer.len() - 2]);
+        assert_eq!(
+            result.unwrap_err(),
+            DictionaryError::UnexpectedEof {
+                section: DictionarySection::Mapping,
+                offset: 68
+            }
+        );
+        assert!(CompactDictionary::try_from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn try_from_bytes_reports_inconsistent_sizes() {
+        let mut buffer = build_small_dict();
+        // マッピングの要素数を書き換える
+        buffer[67] = 2;
+        let result = CompactDictionary::try_from_bytes(&buffer);
+        assert_eq!(
+            result.unwrap_err(),
+            DictionaryError::SizeMismatch {
+                section: DictionarySection::Mapping,
+                offset: 64,
+                expected: 3,
+                actual: 2
+            }
+        );
+    }
+
+    #[test]
+    fn try_from_bytes_reports_invalid_mapping() {
+        let mut buffer = build_small_dict();
+        // マッピングの最後の値を、値トライに存在しないノードに書き換える
+        let last = buffer.len() - 1;
+        buffer[last] = 0x7F;
+        let result = CompactDictionary::try_from_bytes(&buffer);
+        assert_eq!(
+            result.unwrap_err(),
+            DictionaryError::InvalidMapping {
+                offset: last - 3,
+                value: 0x7F
+            }
+        );
+    }
+
+    #[test]
+    fn try_from_bytes_reports_malformed_trie() {
+        let mut buffer = build_small_dict();
+        // キーのトライのビット列を壊す
+        buffer[21] = 0xFF;
+        let result = CompactDictionary::try_from_bytes(&buffer);
+        assert_eq!(
+            result.unwrap_err(),
+            DictionaryError::MalformedTrie {
+                section: DictionarySection::KeyTrie,
+                offset: 10
+            }
+        );
+    }
+
+    #[test]
+    fn borrow_checks_structure_as_requested() {
+        let mut buffer = build_small_dict();
+        buffer[21] = 0xFF;
+        // ヘッダーのない辞書ファイルは、CRC-32の代わりに構造を検査する
+        assert!(CompactDictionaryRef::try_borrow_from_bytes(&buffer).is_err());
+        assert!(CompactDictionaryRef::borrow_from_bytes_trusting_checksum(&buffer).is_err());
+        assert!(CompactDictionaryRef::borrow_from_bytes_unchecked(&buffer).is_ok());
+
+        let options = BuildOptions {
+            with_header: true,
+            ..BuildOptions::default()
+        };
+        let mut buffer = build_with_options(dict_entries(&[("けんさく", &["検索"])]), &options);
+        let word: Vec<u16> = "けんさく".encode_utf16().collect();
+        for dict in [
+            CompactDictionaryRef::try_borrow_from_bytes(&buffer).unwrap(),
+            CompactDictionaryRef::borrow_from_bytes_trusting_checksum(&buffer).unwrap(),
+            CompactDictionaryRef::borrow_from_bytes_unchecked(&buffer).unwrap(),
+        ] {
+            assert_eq!(dict.search(&word).count(), 1);
+        }
+        // ヘッダーのある辞書ファイルは、CRC-32で破損を検出する
+        let last = buffer.len() - 1;
+        buffer[last] ^= 0xFF;
+        assert!(matches!(
+            CompactDictionaryRef::borrow_from_bytes_trusting_checksum(&buffer),
+            Err(DictionaryError::ChecksumMismatch { .. })
+        ));
+        assert!(CompactDictionaryRef::borrow_from_bytes_unchecked(&buffer).is_ok());
+
+        // CRC-32を計算し直した不正なファイルは、構造を検査しなければ読み込めてしまう
+        let mut payload = buffer[HEADER_SIZE..].to_vec();
+        let last = payload.len() - 4;
+        payload[last..].copy_from_slice(&[0, 0, 0, 0]);
+        let mut forged = Vec::new();
+        DictionaryHeader::for_payload(&payload, 0).write_to(&mut forged);
+        forged.extend_from_slice(&payload);
+        assert!(matches!(
+            CompactDictionaryRef::try_borrow_from_bytes(&forged),
+            Err(DictionaryError::InvalidMapping { value: 0, .. })
+        ));
+        assert!(CompactDictionaryRef::borrow_from_bytes_trusting_checksum(&forged).is_ok());
+    }
+
+    #[test]
+    fn borrowed_dictionary_matches_owned() {
+        let mut f = File::open("todofuken").expect("Fail to load dict file");
+        let mut buf = Vec::new();
+        let _ = f.read_to_end(&mut buf);
+        drop(f);
+        let borrowed = CompactDictionaryRef::try_borrow_from_bytes(&buf).unwrap();
+        let owned = CompactDictionary::try_from_bytes(&buf).unwrap();
+        for key in [
+            "おおさか",
+            "おおさかふ",
+            "とうきょうと",
+            "ほっかいどう",
+            "あ",
+            "お",
+            "か",
+            "",
+        ] {
+            let key: Vec<u16> = key.encode_utf16().collect();
+            assert_eq!(
+                borrowed.search(&key).collect::<Vec<_>>(),
+                owned.search(&key).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                borrowed.predictive_search(&key).collect::<Vec<_>>(),
+                owned.predictive_search(&key).collect::<Vec<_>>()
+            );
+        }
+        let key: Vec<u16> = "おおさかふ".encode_utf16().collect();
+        let expected: Vec<u16> = "大阪府".encode_utf16().collect();
+        assert_eq!(borrowed.search(&key).collect::<Vec<_>>(), vec![expected]);
+    }
+
+    #[test]
+    fn borrowed_dictionary_reports_same_errors() {
+        let buffer = build_small_dict();
+        assert_eq!(
+            CompactDictionaryRef::try_borrow_from_bytes(&buffer[..30]).unwrap_err(),
+            CompactDictionary::try_from_bytes(&buffer[..30]).unwrap_err()
+        );
+        let dict = CompactDictionaryRef::try_borrow_from_bytes(&buffer).unwrap();
+        let word: Vec<u16> = "けんさ".encode_utf16().collect();
+        assert_eq!(dict.predictive_search(&word).count(), 3);
+        assert_eq!(dict.into_owned().predictive_search(&word).count(), 3);
+    }
+
+    fn build_small_dict_with_header() -> Vec<u8> {
+        build_with_options(
+            dict_entries(SMALL_ENTRIES),
+            &BuildOptions {
+                with_header: true,
+                ..BuildOptions::default()
+            },
+        )
+    }
+
+    #[test]
+    fn try_from_bytes_accepts_header() {
+        let buffer = build_small_dict_with_header();
+        let dict = CompactDictionary::try_from_bytes(&buffer).unwrap();
+        assert_eq!(dict.header().map(|h| h.version), Some(FORMAT_VERSION));
+        let word: Vec<u16> = "けんさく".encode_utf16().collect();
+        assert_eq!(dict.search(&word).count(), 2);
+        // ヘッダーのない辞書ファイルも読み込める
+        let dict = CompactDictionary::try_from_bytes(&build_small_dict()).unwrap();
+        assert_eq!(dict.header(), None);
+    }
+
+    #[test]
+    fn try_from_bytes_verifies_header() {
+        let buffer = build_small_dict_with_header();
+
+        let mut corrupted = buffer.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0x01;
+        assert!(matches!(
+            CompactDictionary::try_from_bytes(&corrupted),
+            Err(DictionaryError::ChecksumMismatch { .. })
+        ));
+
+        let mut newer = buffer.clone();
+        newer[9] = 2;
+        assert_eq!(
+            CompactDictionary::try_from_bytes(&newer).unwrap_err(),
+            DictionaryError::UnsupportedVersion { version: 2 }
+        );
+
+        let mut flagged = buffer.clone();
+        flagged[10] = 0x80;
+        assert_eq!(
+            CompactDictionary::try_from_bytes(&flagged).unwrap_err(),
+            DictionaryError::UnsupportedFlags { flags: 0x8000 }
+        );
+
+        let truncated = &buffer[..buffer.len() - 4];
+        assert_eq!(
+            CompactDictionary::try_from_bytes(truncated).unwrap_err(),
+            DictionaryError::SizeMismatch {
+                section: DictionarySection::Header,
+                offset: 12,
+                expected: buffer.len() - HEADER_SIZE,
+                actual: buffer.len() - HEADER_SIZE - 4
+            }
+        );
+
+        assert_eq!(
+            CompactDictionary::try_from_bytes(&buffer[..HEADER_SIZE - 1]).unwrap_err(),
+            DictionaryError::UnexpectedEof {
+                section: DictionarySection::Header,
+                offset: 0
+            }
+        );
+    }
+
+    fn build_small_dict_with_weights() -> Vec<u8> {
+        let dict = dict_entries(&[("けんさ", &["検査"]), ("けんさく", &["罠索", "検索"])]);
+        let weights = HashMap::from([("検索".to_string(), 100), ("検査".to_string(), 30)]);
+        build_with_options(
+            dict,
+            &BuildOptions {
+                weights: Some(weights),
+                ..BuildOptions::default()
+            },
+        )
+    }
+
+    #[test]
+    fn weights_round_trip_through_builder() {
+        let buffer = build_small_dict_with_weights();
+        let dict = CompactDictionary::try_from_bytes(&buffer).unwrap();
+        assert!(dict.has_weights());
+        assert_eq!(dict.header().map(|h| h.flags), Some(FLAG_WEIGHTS));
+        let to_strings = |words: Vec<(Vec<u16>, u32)>| -> Vec<(String, u32)> {
+            words
+                .into_iter()
+                .map(|(w, weight)| (String::from_utf16_lossy(&w), weight))
+                .collect()
+        };
+        // 読みごとの単語は重みの大きい順に格納される
+        let word: Vec<u16> = "けんさく".encode_utf16().collect();
+        assert_eq!(
+            to_strings(dict.search_weighted(&word).collect()),
+            vec![("検索".to_string(), 100), ("罠索".to_string(), 0)]
+        );
+        let word: Vec<u16> = "けんさ".encode_utf16().collect();
+        let weighted = to_strings(dict.predictive_search_weighted(&word).collect());
+        assert_eq!(weighted.len(), 3);
+        assert!(weighted.contains(&("検査".to_string(), 30)));
+        let words: Vec<String> = dict
+            .predictive_search(&word)
+            .map(|w| String::from_utf16_lossy(&w))
+            .collect();
+        assert_eq!(words, weighted.iter().map(|(w, _)| w.clone()).collect::<Vec<_>>());
+
+        let borrowed = CompactDictionaryRef::try_borrow_from_bytes(&buffer).unwrap();
+        assert_eq!(
+            borrowed.predictive_search_weighted(&word).collect::<Vec<_>>(),
+            dict.predictive_search_weighted(&word).collect::<Vec<_>>()
+        );
+        // 重みを持たない辞書では、重みはすべて0になる
+        let dict = CompactDictionary::try_from_bytes(&build_small_dict()).unwrap();
+        assert!(!dict.has_weights());
+        assert!(dict.predictive_search_weighted(&word).all(|(_, weight)| weight == 0));
+    }
+
+    #[test]
+    fn try_from_bytes_reports_inconsistent_weights() {
+        let mut buffer = build_small_dict_with_weights();
+        // 重みの要素数を書き換え、ヘッダーのCRC-32も合わせる
+        let weights_offset = buffer.len() - 4 * 3 - 4;
+        buffer[weights_offset + 3] = 2;
+        let header = DictionaryHeader::for_payload(&buffer[HEADER_SIZE..], FLAG_WEIGHTS);
+        let mut with_header = Vec::new();
+        header.write_to(&mut with_header);
+        with_header.extend_from_slice(&buffer[HEADER_SIZE..]);
+        assert_eq!(
+            CompactDictionary::try_from_bytes(&with_header).unwrap_err(),
+            DictionaryError::SizeMismatch {
+                section: DictionarySection::Weights,
+                offset: weights_offset,
+                expected: 3,
+                actual: 2
+            }
+        );
+    }
+
+    #[test]
+    fn entries_round_trip_through_builder() {
+        let mut f = File::open("todofuken").expect("Fail to load dict file");
+        let mut buf = Vec::new();
+        let _ = f.read_to_end(&mut buf);
+        drop(f);
+        let dict = CompactDictionary::new(&buf);
+        let entries: HashMap<String, Vec<String>> = dict.entries().collect();
+        // 古いビルダーで作られたため、最後のノードの値はビットベクトルから参照されていない
+        assert_eq!(entries.len(), 46);
+        assert_eq!(entries["おおさかふ"], vec!["大阪府"]);
+
+        let rebuilt = CompactDictionary::new(&build(entries.clone()));
+        let rebuilt_entries: HashMap<String, Vec<String>> = rebuilt.entries().collect();
+        assert_eq!(rebuilt_entries, entries);
+        let borrowed = CompactDictionaryRef::try_borrow_from_bytes(&buf).unwrap();
+        assert_eq!(borrowed.entries().collect::<HashMap<_, _>>(), entries);
+    }
 }
@@ -2,9 +2,13 @@ use std::collections::HashMap;
 
 use byteorder::{BigEndian, WriteBytesExt};
 
-use super::{bit_list::BitList, louds_trie::LoudsTrie};
+use super::{
+    bit_list::BitList,
+    dictionary_header::{DictionaryHeader, FLAG_WEIGHTS, HEADER_SIZE},
+    louds_trie::LoudsTrie,
+};
 
-fn encode_char(c: char) -> Option<u16> {
+pub(crate) fn encode_char(c: char) -> Option<u16> {
     if c == '\u{00}' {
         return Some(0);
     }
@@ -24,23 +28,52 @@ fn encode_char(c: char) -> Option<u16> {
     return None;
 }
 
-pub fn build(mut dict: HashMap<String, Vec<String>>) -> Vec<u8> {
+/// 辞書ファイルを出力する際のオプション
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// マジックバイト、バージョン、CRC-32を含むヘッダーを先頭に出力する
+    pub with_header: bool,
+    /// 単語ごとの重み (出現頻度など)。指定すると、読みごとの単語を重みの大きい順に並べ、
+    /// 重みのセクションも出力する。重みは機能フラグで示すため、`with_header`によらずヘッダーを出力する。
+    /// 一覧にない単語の重みは0になる。
+    pub weights: Option<HashMap<String, u32>>,
+}
+
+/// ヘッダーのない辞書ファイルを出力する
+pub fn build(dict: HashMap<String, Vec<String>>) -> Vec<u8> {
+    build_with_options(dict, &BuildOptions::default())
+}
+
+/// 辞書ファイルを出力した結果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildReport {
+    /// 辞書ファイルで表現できない文字を含むため、読み飛ばした読み (辞書順)
+    pub skipped_keys: Vec<String>,
+}
+
+pub fn build_with_options(dict: HashMap<String, Vec<String>>, options: &BuildOptions) -> Vec<u8> {
+    build_with_report(dict, options).0
+}
+
+/// `build_with_options`と同じ辞書ファイルを出力し、読み飛ばした読みも返す
+pub fn build_with_report(mut dict: HashMap<String, Vec<String>>, options: &BuildOptions) -> (Vec<u8>, BuildReport) {
     // remove some keys
-    let mut keys_to_remove = Vec::new();
-    for key in dict.keys() {
-        for c in key.chars() {
-            let encoded = encode_char(c);
-            match encoded {
-                Some(_) => {}
-                None => {
-                    keys_to_remove.push(key.clone());
-                    println!("skipped the word: {}", key);
-                }
-            }
-        }
+    let mut skipped_keys: Vec<String> = dict
+        .keys()
+        .filter(|key| key.chars().any(|c| encode_char(c).is_none()))
+        .cloned()
+        .collect();
+    skipped_keys.sort();
+    for key in &skipped_keys {
+        dict.remove(key);
     }
-    for key in keys_to_remove {
-        dict.remove(&key);
+    let report = BuildReport { skipped_keys };
+    let weight_of = |word: &String| options.weights.as_ref().and_then(|w| w.get(word)).copied().unwrap_or(0);
+    if options.weights.is_some() {
+        // 重みの大きい単語から引けるように、読みごとに並べ替える
+        for values in dict.values_mut() {
+            values.sort_by_key(|v| std::cmp::Reverse(weight_of(v)));
+        }
     }
 
     // build key trie
@@ -65,6 +98,7 @@ pub fn build(mut dict: HashMap<String, Vec<String>>) -> Vec<u8> {
         mapping_count += i.len();
     }
     let mut mapping: Vec<u32> = vec![0; mapping_count];
+    let mut weights: Vec<u32> = vec![0; mapping_count];
     let mut mapping_index = 0;
     let mut mapping_bit_list = BitList::new();
     for i in 1..=key_trie.size() + 1 {
@@ -75,6 +109,7 @@ pub fn build(mut dict: HashMap<String, Vec<String>>) -> Vec<u8> {
                 mapping_bit_list.push(true);
                 let a: Vec<u16> = values[j].encode_utf16().collect();
                 mapping[mapping_index] = value_trie.get(&a).unwrap() as u32;
+                weights[mapping_index] = weight_of(&values[j]);
                 mapping_index += 1;
             }
         }
@@ -84,7 +119,12 @@ pub fn build(mut dict: HashMap<String, Vec<String>>) -> Vec<u8> {
     let key_trie_data_size = 8 + key_trie.edges.len() + ((key_trie.bit_vector.size() + 63) >> 6) * 8;
     let value_trie_data_size = 8 + value_trie.edges.len() * 2 + ((value_trie.bit_vector.size() + 63) >> 6) * 8;
     let mapping_data_size = 8 + ((mapping_bit_list.len() + 63) >> 6) * 8 + mapping.len() * 4;
-    let output_data_size = key_trie_data_size + value_trie_data_size + mapping_data_size;
+    let weights_data_size = if options.weights.is_some() {
+        4 + weights.len() * 4
+    } else {
+        0
+    };
+    let output_data_size = key_trie_data_size + value_trie_data_size + mapping_data_size + weights_data_size;
 
     // ready output
     let mut output_data: Vec<u8> = Vec::with_capacity(output_data_size);
@@ -129,6 +169,14 @@ pub fn build(mut dict: HashMap<String, Vec<String>>) -> Vec<u8> {
         output_data.write_u32::<BigEndian>(value).unwrap();
     }
 
+    // output weights
+    if options.weights.is_some() {
+        output_data.write_i32::<BigEndian>(weights.len() as i32).unwrap();
+        for weight in weights {
+            output_data.write_u32::<BigEndian>(weight).unwrap();
+        }
+    }
+
     // check data size
     let data_view_index = output_data.len();
     if data_view_index != output_data_size {
@@ -137,7 +185,17 @@ pub fn build(mut dict: HashMap<String, Vec<String>>) -> Vec<u8> {
             output_data_size, data_view_index
         );
     }
-    return output_data;
+
+    // output header
+    if options.with_header || options.weights.is_some() {
+        let flags = if options.weights.is_some() { FLAG_WEIGHTS } else { 0 };
+        let header = DictionaryHeader::for_payload(&output_data, flags);
+        let mut with_header = Vec::with_capacity(HEADER_SIZE + output_data.len());
+        header.write_to(&mut with_header);
+        with_header.extend_from_slice(&output_data);
+        return (with_header, report);
+    }
+    (output_data, report)
 }
 
 mod tests {
@@ -170,4 +228,37 @@ mod tests {
         ];
         assert_eq!(buffer, expected_buffer);
     }
+
+    #[test]
+    fn test_build_with_header() {
+        use super::{BuildOptions, build, build_with_options};
+        use crate::migemo::dictionary_header::{DictionaryHeader, HEADER_SIZE};
+        use std::collections::HashMap;
+        let mut dict = HashMap::new();
+        dict.insert("けんさ".to_string(), vec!["検査".to_string()]);
+        let without_header = build(dict.clone());
+        let with_header = build_with_options(
+            dict,
+            &BuildOptions {
+                with_header: true,
+                ..BuildOptions::default()
+            },
+        );
+        assert_eq!(&with_header[HEADER_SIZE..], &without_header[..]);
+        let header = DictionaryHeader::parse(&with_header).unwrap();
+        assert_eq!(header, DictionaryHeader::for_payload(&without_header, 0));
+    }
+
+    #[test]
+    fn test_build_reports_skipped_keys() {
+        use super::{BuildOptions, build_with_report};
+        use std::collections::HashMap;
+        let mut dict = HashMap::new();
+        dict.insert("けんさ".to_string(), vec!["検査".to_string()]);
+        // 辞書ファイルで表現できない文字を複数含む読みも、一度だけ報告する
+        dict.insert("ケンサ".to_string(), vec!["検査".to_string()]);
+        dict.insert("ヴぁ".to_string(), vec!["ヴァ".to_string()]);
+        let (_, report) = build_with_report(dict, &BuildOptions::default());
+        assert_eq!(report.skipped_keys, vec!["ケンサ".to_string(), "ヴぁ".to_string()]);
+    }
 }
@@ -0,0 +1,264 @@
+use super::compact_dictionary::{CompactDictionary, DictionaryStorage, FuzzyMatch};
+use std::collections::HashSet;
+
+/// 読みから単語を引く辞書
+///
+/// `query`に渡す辞書はこのトレイトを実装していればよく、複数の辞書を`LayeredDictionary`で重ねることもできる。
+pub trait Dictionary {
+    /// 読みに完全一致する単語を返す
+    fn search<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = Vec<u16>> + 'a>;
+
+    /// 読みが前方一致する単語を返す
+    fn predictive_search<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = Vec<u16>> + 'a>;
+
+    /// 単語に重み (出現頻度など) が付いているか
+    fn has_weights(&self) -> bool {
+        false
+    }
+
+    /// `search`と同じ単語を、重みとともに返す。重みを持たない辞書では、重みはすべて0になる。
+    fn search_weighted<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = (Vec<u16>, u32)> + 'a> {
+        Box::new(self.search(key).map(|word| (word, 0)))
+    }
+
+    /// `predictive_search`と同じ単語を、重みとともに返す。重みを持たない辞書では、重みはすべて0になる。
+    fn predictive_search_weighted<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = (Vec<u16>, u32)> + 'a> {
+        Box::new(self.predictive_search(key).map(|word| (word, 0)))
+    }
+
+    /// 読みとの編集距離が`max_edits`以下の読みを持つ単語を、編集距離の小さい順に返す。`predictive`が`true`の場合は、
+    /// 近い読みで前方一致検索した単語も返す。近似検索に対応しない辞書では何も返さない。
+    fn fuzzy_search_weighted<'a>(
+        &'a self,
+        _key: &[u16],
+        _max_edits: usize,
+        _predictive: bool,
+    ) -> Box<dyn Iterator<Item = FuzzyMatch> + 'a> {
+        Box::new(std::iter::empty())
+    }
+}
+
+impl<S: DictionaryStorage> Dictionary for CompactDictionary<S> {
+    fn search<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = Vec<u16>> + 'a> {
+        Box::new(CompactDictionary::search(self, key))
+    }
+
+    fn predictive_search<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = Vec<u16>> + 'a> {
+        Box::new(CompactDictionary::predictive_search(self, key))
+    }
+
+    fn has_weights(&self) -> bool {
+        CompactDictionary::has_weights(self)
+    }
+
+    fn search_weighted<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = (Vec<u16>, u32)> + 'a> {
+        Box::new(CompactDictionary::search_weighted(self, key))
+    }
+
+    fn predictive_search_weighted<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = (Vec<u16>, u32)> + 'a> {
+        Box::new(CompactDictionary::predictive_search_weighted(self, key))
+    }
+
+    fn fuzzy_search_weighted<'a>(
+        &'a self,
+        key: &[u16],
+        max_edits: usize,
+        predictive: bool,
+    ) -> Box<dyn Iterator<Item = FuzzyMatch> + 'a> {
+        Box::new(CompactDictionary::fuzzy_search_weighted(
+            self, key, max_edits, predictive,
+        ))
+    }
+}
+
+// `Dictionary`のすべてのメソッドを、`$inner`で得た辞書にそのまま転送する。
+// 転送する実装をここにまとめておき、トレイトにメソッドを加えたときに転送し忘れて既定の実装が使われないようにする。
+macro_rules! forward_dictionary {
+    (|$this:ident| $inner:expr) => {
+        fn search<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = Vec<u16>> + 'a> {
+            let $this = self;
+            $crate::migemo::dictionary::Dictionary::search($inner, key)
+        }
+
+        fn predictive_search<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = Vec<u16>> + 'a> {
+            let $this = self;
+            $crate::migemo::dictionary::Dictionary::predictive_search($inner, key)
+        }
+
+        fn has_weights(&self) -> bool {
+            let $this = self;
+            $crate::migemo::dictionary::Dictionary::has_weights($inner)
+        }
+
+        fn search_weighted<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = (Vec<u16>, u32)> + 'a> {
+            let $this = self;
+            $crate::migemo::dictionary::Dictionary::search_weighted($inner, key)
+        }
+
+        fn predictive_search_weighted<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = (Vec<u16>, u32)> + 'a> {
+            let $this = self;
+            $crate::migemo::dictionary::Dictionary::predictive_search_weighted($inner, key)
+        }
+
+        fn fuzzy_search_weighted<'a>(
+            &'a self,
+            key: &[u16],
+            max_edits: usize,
+            predictive: bool,
+        ) -> Box<dyn Iterator<Item = $crate::migemo::compact_dictionary::FuzzyMatch> + 'a> {
+            let $this = self;
+            $crate::migemo::dictionary::Dictionary::fuzzy_search_weighted($inner, key, max_edits, predictive)
+        }
+    };
+}
+pub(crate) use forward_dictionary;
+
+impl<D: Dictionary + ?Sized> Dictionary for &D {
+    forward_dictionary!(|this| &**this);
+}
+
+impl<D: Dictionary + ?Sized> Dictionary for Box<D> {
+    forward_dictionary!(|this| &**this);
+}
+
+/// 複数の辞書を重ねて、1つの辞書として引く
+///
+/// 先に追加した辞書の結果から順に返し、同じ単語は一度だけ返す。
+/// 重みを返す場合も、同じ単語には先に追加した辞書での重みを使う。
+#[derive(Default)]
+pub struct LayeredDictionary<'a> {
+    layers: Vec<Box<dyn Dictionary + Send + Sync + 'a>>,
+}
+
+impl<'a> LayeredDictionary<'a> {
+    pub fn new() -> LayeredDictionary<'a> {
+        LayeredDictionary { layers: Vec::new() }
+    }
+
+    /// 辞書を一番下の層に追加する
+    pub fn push<D: Dictionary + Send + Sync + 'a>(&mut self, dictionary: D) {
+        self.layers.push(Box::new(dictionary));
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    // 各層の結果を順につなげ、`word`で取り出した単語が重複するものを取り除く
+    fn merge<'b, T: 'b, F>(
+        &'b self,
+        key: &[u16],
+        search: F,
+        word: fn(&T) -> &Vec<u16>,
+    ) -> Box<dyn Iterator<Item = T> + 'b>
+    where
+        F: Fn(&'b (dyn Dictionary + Send + Sync + 'a), &[u16]) -> Box<dyn Iterator<Item = T> + 'b> + 'b,
+    {
+        let key = key.to_vec();
+        let mut seen = HashSet::new();
+        Box::new(
+            self.layers
+                .iter()
+                .flat_map(move |layer| search(layer.as_ref(), &key))
+                .filter(move |item| seen.insert(word(item).clone())),
+        )
+    }
+}
+
+impl Dictionary for LayeredDictionary<'_> {
+    fn search<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = Vec<u16>> + 'a> {
+        self.merge(key, |layer, key| layer.search(key), |word| word)
+    }
+
+    fn predictive_search<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = Vec<u16>> + 'a> {
+        self.merge(key, |layer, key| layer.predictive_search(key), |word| word)
+    }
+
+    fn has_weights(&self) -> bool {
+        self.layers.iter().any(|layer| layer.has_weights())
+    }
+
+    fn search_weighted<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = (Vec<u16>, u32)> + 'a> {
+        self.merge(key, |layer, key| layer.search_weighted(key), |(word, _)| word)
+    }
+
+    fn predictive_search_weighted<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = (Vec<u16>, u32)> + 'a> {
+        self.merge(
+            key,
+            |layer, key| layer.predictive_search_weighted(key),
+            |(word, _)| word,
+        )
+    }
+
+    fn fuzzy_search_weighted<'a>(
+        &'a self,
+        key: &[u16],
+        max_edits: usize,
+        predictive: bool,
+    ) -> Box<dyn Iterator<Item = FuzzyMatch> + 'a> {
+        // 編集距離の小さい順を保つように各層の結果を混ぜ、同じ近さなら上の層を先にする
+        let mut layers: Vec<_> = self
+            .layers
+            .iter()
+            .map(|layer| layer.fuzzy_search_weighted(key, max_edits, predictive).peekable())
+            .collect();
+        let mut seen = HashSet::new();
+        Box::new(
+            std::iter::from_fn(move || {
+                let nearest = layers
+                    .iter_mut()
+                    .enumerate()
+                    .filter_map(|(i, layer)| layer.peek().map(|m| (m.distance, i)))
+                    .min()?;
+                layers[nearest.1].next()
+            })
+            .filter(move |m| seen.insert(m.word.clone())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migemo::query::query;
+    use crate::migemo::regex_generator::RegexOperator;
+    use crate::migemo::test_support::build_dict;
+
+    fn to_strings(words: Box<dyn Iterator<Item = Vec<u16>> + '_>) -> Vec<String> {
+        words.map(|w| String::from_utf16_lossy(&w)).collect()
+    }
+
+    #[test]
+    fn layered_dictionary_deduplicates_results() {
+        let general = build_dict(&[("けんさ", &["検査"]), ("けんさく", &["検索", "研削"])]);
+        let jargon = build_dict(&[("けんさく", &["検索", "献策"]), ("けんさくき", &["検索機"])]);
+        let mut layered = LayeredDictionary::new();
+        layered.push(&general);
+        layered.push(jargon);
+        assert_eq!(layered.len(), 2);
+
+        let key: Vec<u16> = "けんさく".encode_utf16().collect();
+        assert_eq!(to_strings(layered.search(&key)), vec!["検索", "研削", "献策"]);
+        let mut words = to_strings(layered.predictive_search(&key));
+        words.sort();
+        let mut expected = vec!["検索", "研削", "献策", "検索機"];
+        expected.sort();
+        assert_eq!(words, expected);
+    }
+
+    #[test]
+    fn query_accepts_layered_dictionary() {
+        let general = build_dict(&[("けんさく", &["検索"])]);
+        let jargon = build_dict(&[("けんさく", &["献策"])]);
+        let mut layered = LayeredDictionary::new();
+        layered.push(general);
+        layered.push(jargon);
+        let result = query("kensaku".to_string(), &layered, &RegexOperator::Default);
+        assert!(result.contains("検索"));
+        assert!(result.contains("献策"));
+    }
+}
@@ -0,0 +1,125 @@
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+
+/// ヘッダーの先頭に置くマジックバイト
+pub const MAGIC: &[u8; 8] = b"MIGEMOCD";
+
+/// このクレートが読み書きできるフォーマットのバージョン
+pub const FORMAT_VERSION: u16 = 1;
+
+/// マッピングの後ろに、マッピングと同じ順で単語の重み (出現頻度など) を格納している
+pub const FLAG_WEIGHTS: u16 = 0x0001;
+
+/// このクレートが解釈できる機能フラグ
+pub const KNOWN_FLAGS: u16 = FLAG_WEIGHTS;
+
+/// ヘッダーのバイト数
+pub const HEADER_SIZE: usize = 20;
+
+const CRC32_TABLE: [u32; 256] = build_crc32_table();
+
+const fn build_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0usize;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 { 0xEDB88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-32 (IEEE 802.3) を計算する
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for &b in bytes {
+        crc = CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// 辞書ファイルの先頭に置く、省略可能なヘッダー
+///
+/// | オフセット | サイズ | 内容 |
+/// |---|---|---|
+/// | 0 | 8 | マジックバイト `MIGEMOCD` |
+/// | 8 | 2 | フォーマットのバージョン |
+/// | 10 | 2 | 機能フラグ |
+/// | 12 | 4 | ヘッダーに続くペイロードのバイト数 |
+/// | 16 | 4 | ペイロードのCRC-32 |
+///
+/// 数値はすべてビッグエンディアンで格納する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DictionaryHeader {
+    pub version: u16,
+    pub flags: u16,
+    pub payload_length: u32,
+    pub checksum: u32,
+}
+
+impl DictionaryHeader {
+    /// ペイロードから、現在のバージョンのヘッダーを作る
+    pub fn for_payload(payload: &[u8], flags: u16) -> DictionaryHeader {
+        DictionaryHeader {
+            version: FORMAT_VERSION,
+            flags,
+            payload_length: payload.len() as u32,
+            checksum: crc32(payload),
+        }
+    }
+
+    /// バイト列がヘッダーで始まっているかを調べる
+    ///
+    /// ヘッダーのない古い辞書ファイルは、キーのトライのエッジ数から始まるため、マジックバイトと一致することはない。
+    pub fn is_present(buffer: &[u8]) -> bool {
+        buffer.starts_with(MAGIC)
+    }
+
+    /// ヘッダーを読み取る。バイト数が足りない場合は`None`を返す。
+    pub fn parse(buffer: &[u8]) -> Option<DictionaryHeader> {
+        if buffer.len() < HEADER_SIZE || !DictionaryHeader::is_present(buffer) {
+            return None;
+        }
+        Some(DictionaryHeader {
+            version: BigEndian::read_u16(&buffer[8..10]),
+            flags: BigEndian::read_u16(&buffer[10..12]),
+            payload_length: BigEndian::read_u32(&buffer[12..16]),
+            checksum: BigEndian::read_u32(&buffer[16..20]),
+        })
+    }
+
+    pub fn write_to(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(MAGIC);
+        output.write_u16::<BigEndian>(self.version).unwrap();
+        output.write_u16::<BigEndian>(self.flags).unwrap();
+        output.write_u32::<BigEndian>(self.payload_length).unwrap();
+        output.write_u32::<BigEndian>(self.checksum).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_reference_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414FA339);
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = DictionaryHeader::for_payload(b"payload", 0);
+        let mut buffer = Vec::new();
+        header.write_to(&mut buffer);
+        assert_eq!(buffer.len(), HEADER_SIZE);
+        assert_eq!(DictionaryHeader::parse(&buffer), Some(header));
+        assert_eq!(DictionaryHeader::parse(&buffer[..HEADER_SIZE - 1]), None);
+        assert_eq!(DictionaryHeader::parse(&[0, 0, 0, 6]), None);
+    }
+}
@@ -0,0 +1,459 @@
+use super::compact_dictionary::{CompactDictionaryRef, DictionaryError};
+use super::dictionary::{Dictionary, LayeredDictionary, forward_dictionary};
+use super::matcher::{CandidateMatcher, Match, Separator};
+use super::query::{
+    Candidate, QueryOptions, QueryReport, SearchMode, operator_detail, query_candidates_with_romaji, query_with_romaji,
+    tokenize,
+};
+use super::regex_generator::RegexOperator;
+use super::romaji_processor::RomajiProcessor;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// `MigemoBuilder::build`が失敗した理由
+#[derive(Debug)]
+pub enum MigemoError {
+    /// 辞書ファイルを読み込めない
+    Io { path: PathBuf, source: io::Error },
+    /// 辞書の形式が正しくない。バイト列から読み込んだ場合は`path`が`None`になる。
+    Dictionary {
+        path: Option<PathBuf>,
+        source: DictionaryError,
+    },
+}
+
+impl fmt::Display for MigemoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigemoError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            MigemoError::Dictionary {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            MigemoError::Dictionary { path: None, source } => write!(f, "{}", source),
+        }
+    }
+}
+
+impl std::error::Error for MigemoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigemoError::Io { source, .. } => Some(source),
+            MigemoError::Dictionary { source, .. } => Some(source),
+        }
+    }
+}
+
+// `build`まで読み込みを遅らせる辞書
+enum DictionarySource<'a> {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+    Ref(&'a [u8]),
+    Dictionary(Box<dyn Dictionary + Send + Sync + 'a>),
+}
+
+// 辞書ファイルのバイト列。どちらも、所有者をムーブしても中身のアドレスは変わらない
+enum DictionaryBytes {
+    Heap(Box<[u8]>),
+    #[cfg(feature = "mmap")]
+    Mapped(Mmap),
+}
+
+impl DictionaryBytes {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            DictionaryBytes::Heap(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            DictionaryBytes::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+// バイト列を持ち、展開せずにそのバイト列から辞書を引く
+struct OwnedBytesDictionary {
+    // `_bytes`を借用しているので、`_bytes`より先に破棄されるように先に宣言する
+    dictionary: CompactDictionaryRef<'static>,
+    _bytes: DictionaryBytes,
+}
+
+impl OwnedBytesDictionary {
+    fn new(bytes: DictionaryBytes) -> Result<OwnedBytesDictionary, DictionaryError> {
+        let slice = bytes.as_slice();
+        // SAFETY: 中身はヒープかマップした領域にあり、`bytes`をムーブしても動かない。
+        // 借用した辞書は外に出さず、`bytes`と同じ構造体に入れて、`bytes`より先に破棄する。
+        let slice: &'static [u8] = unsafe { std::slice::from_raw_parts(slice.as_ptr(), slice.len()) };
+        let dictionary = CompactDictionaryRef::try_borrow_from_bytes(slice)?;
+        Ok(OwnedBytesDictionary {
+            dictionary,
+            _bytes: bytes,
+        })
+    }
+}
+
+impl Dictionary for OwnedBytesDictionary {
+    forward_dictionary!(|this| &this.dictionary);
+}
+
+// 辞書ファイルを読み込む。`mmap`フィーチャーが有効なら、ファイルをメモリにマップする
+fn read_dictionary_file(path: &Path) -> io::Result<DictionaryBytes> {
+    #[cfg(feature = "mmap")]
+    {
+        let file = std::fs::File::open(path)?;
+        // 辞書ファイルは実行中に書き換えられないものとする
+        let mmap = unsafe { Mmap::map(&file) }?;
+        Ok(DictionaryBytes::Mapped(mmap))
+    }
+    #[cfg(not(feature = "mmap"))]
+    {
+        Ok(DictionaryBytes::Heap(std::fs::read(path)?.into_boxed_slice()))
+    }
+}
+
+/// `Migemo`を組み立てる
+///
+/// 辞書は追加した順に重ねる。辞書を1つも追加しなければ、辞書を引かずに展開する。
+pub struct MigemoBuilder<'a> {
+    dictionaries: Vec<DictionarySource<'a>>,
+    romaji: Option<RomajiProcessor>,
+    operator: RegexOperator,
+    options: QueryOptions,
+}
+
+impl<'a> MigemoBuilder<'a> {
+    pub fn new() -> MigemoBuilder<'a> {
+        MigemoBuilder {
+            dictionaries: Vec::new(),
+            romaji: None,
+            operator: RegexOperator::Default,
+            options: QueryOptions::default(),
+        }
+    }
+
+    /// migemo-compact-dictのファイルを辞書に加える。
+    ///
+    /// ファイルは`build`で読み込む。`mmap`フィーチャーが有効ならメモリにマップし、そうでなければ全体を読み込む。
+    /// どちらの場合も展開はせず、ファイルの内容から直接辞書を引く。
+    pub fn dictionary_path<P: AsRef<Path>>(mut self, path: P) -> MigemoBuilder<'a> {
+        self.dictionaries
+            .push(DictionarySource::Path(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// migemo-compact-dictのバイト列を辞書に加える。バイト列はそのまま持ち、展開せずに辞書を引く。
+    pub fn dictionary_bytes<B: Into<Vec<u8>>>(mut self, bytes: B) -> MigemoBuilder<'a> {
+        self.dictionaries.push(DictionarySource::Bytes(bytes.into()));
+        self
+    }
+
+    /// migemo-compact-dictのバイト列を、コピーせずに借用して辞書に加える
+    pub fn dictionary_ref(mut self, bytes: &'a [u8]) -> MigemoBuilder<'a> {
+        self.dictionaries.push(DictionarySource::Ref(bytes));
+        self
+    }
+
+    /// 読み込み済みの辞書を加える。メモリマップしたファイルを借用する場合などに使う。
+    pub fn dictionary<D: Dictionary + Send + Sync + 'a>(mut self, dictionary: D) -> MigemoBuilder<'a> {
+        self.dictionaries
+            .push(DictionarySource::Dictionary(Box::new(dictionary)));
+        self
+    }
+
+    /// ローマ字の変換表。指定しなければ組み込みの変換表を使う。
+    pub fn romaji_processor(mut self, romaji: RomajiProcessor) -> MigemoBuilder<'a> {
+        self.romaji = Some(romaji);
+        self
+    }
+
+    /// 生成する正規表現の方言
+    pub fn operator(mut self, operator: RegexOperator) -> MigemoBuilder<'a> {
+        self.operator = operator;
+        self
+    }
+
+    pub fn options(mut self, options: QueryOptions) -> MigemoBuilder<'a> {
+        self.options = options;
+        self
+    }
+
+    pub fn build(self) -> Result<Migemo<'a>, MigemoError> {
+        let mut dictionary = LayeredDictionary::new();
+        for source in self.dictionaries {
+            match source {
+                DictionarySource::Path(path) => {
+                    let bytes = match read_dictionary_file(&path) {
+                        Ok(bytes) => bytes,
+                        Err(source) => return Err(MigemoError::Io { path, source }),
+                    };
+                    match OwnedBytesDictionary::new(bytes) {
+                        Ok(layer) => dictionary.push(layer),
+                        Err(source) => {
+                            return Err(MigemoError::Dictionary {
+                                path: Some(path),
+                                source,
+                            });
+                        }
+                    }
+                }
+                DictionarySource::Bytes(bytes) => {
+                    match OwnedBytesDictionary::new(DictionaryBytes::Heap(bytes.into_boxed_slice())) {
+                        Ok(layer) => dictionary.push(layer),
+                        Err(source) => return Err(MigemoError::Dictionary { path: None, source }),
+                    }
+                }
+                DictionarySource::Ref(bytes) => match CompactDictionaryRef::try_borrow_from_bytes(bytes) {
+                    Ok(layer) => dictionary.push(layer),
+                    Err(source) => return Err(MigemoError::Dictionary { path: None, source }),
+                },
+                DictionarySource::Dictionary(layer) => dictionary.push(layer),
+            }
+        }
+        Ok(Migemo {
+            dictionary,
+            romaji: self.romaji.unwrap_or_else(RomajiProcessor::new),
+            operator: self.operator,
+            options: self.options,
+        })
+    }
+}
+
+impl Default for MigemoBuilder<'_> {
+    fn default() -> Self {
+        MigemoBuilder::new()
+    }
+}
+
+/// 辞書、ローマ字の変換表、正規表現の方言、展開の設定をまとめて持ち、検索語を展開する
+///
+/// `Send + Sync`なので、1つ作っておけば複数のスレッドから共有できる。
+///
+/// ```
+/// use rustmigemo::migemo::Migemo;
+/// let migemo = Migemo::builder().build().unwrap();
+/// // 辞書がなくても、ひらがなやカタカナには一致する
+/// assert!(migemo.is_match("ケンサクの話", "kensaku"));
+/// ```
+pub struct Migemo<'a> {
+    dictionary: LayeredDictionary<'a>,
+    romaji: RomajiProcessor,
+    operator: RegexOperator,
+    options: QueryOptions,
+}
+
+impl<'a> Migemo<'a> {
+    pub fn builder() -> MigemoBuilder<'a> {
+        MigemoBuilder::new()
+    }
+
+    pub fn operator(&self) -> &RegexOperator {
+        &self.operator
+    }
+
+    pub fn options(&self) -> &QueryOptions {
+        &self.options
+    }
+
+    /// 検索語から、トークンがこの順に並んでいるものにマッチする正規表現を生成する
+    pub fn query(&self, word: &str) -> String {
+        self.query_with_options(word, &self.options)
+    }
+
+    /// 組み立てたときの設定の代わりに`options`に従って、`query`と同じ正規表現を生成する
+    pub fn query_with_options(&self, word: &str, options: &QueryOptions) -> String {
+        query_with_romaji(
+            word,
+            &self.dictionary,
+            &self.romaji,
+            &self.operator,
+            options,
+            SearchMode::Phrase,
+        )
+        .patterns
+        .concat()
+    }
+
+    /// 検索の方法に従って正規表現の一覧を生成し、候補を減らしたかどうかも返す
+    pub fn query_with_report(&self, word: &str, mode: SearchMode) -> QueryReport {
+        query_with_romaji(
+            word,
+            &self.dictionary,
+            &self.romaji,
+            &self.operator,
+            &self.options,
+            mode,
+        )
+    }
+
+    /// 検索語をトークンごとに展開し、すべての候補を返す
+    pub fn candidates(&self, word: &str) -> Vec<Candidate> {
+        tokenize(word)
+            .flat_map(|token| query_candidates_with_romaji(token, &self.dictionary, &self.romaji, &self.options))
+            .collect()
+    }
+
+    /// テキストに、検索語のトークンがこの順に並んでいる箇所があるかどうか
+    ///
+    /// 正規表現を使わずに候補を直接探すが、`query`の正規表現と同じく、正規表現の方言と`QueryOptions::whitespace`に
+    /// 従って、文字やトークンの間に挟まった改行や空白も許す。
+    pub fn is_match(&self, text: &str, word: &str) -> bool {
+        let matchers: Vec<CandidateMatcher> = tokenize(word)
+            .map(|token| {
+                CandidateMatcher::new(query_candidates_with_romaji(
+                    token,
+                    &self.dictionary,
+                    &self.romaji,
+                    &self.options,
+                ))
+            })
+            .collect();
+        if matchers.is_empty() {
+            return false;
+        }
+        let separator = Separator::from_pattern(&operator_detail(&self.operator, &self.options).newline);
+        text.char_indices()
+            .any(|(start, _)| phrase_matches(&matchers, &text[start..], separator))
+    }
+
+    /// テキストの中でいずれかのトークンに一致した箇所を、先頭から順に返す
+    pub fn find_iter(&self, text: &str, word: &str) -> Vec<Match> {
+        self.find_iter_with_options(text, word, &self.options)
+    }
+
+    /// 組み立てたときの設定の代わりに`options`に従って、`find_iter`と同じ箇所を返す
+    pub fn find_iter_with_options(&self, text: &str, word: &str, options: &QueryOptions) -> Vec<Match> {
+        CandidateMatcher::for_query_with_romaji(word, &self.dictionary, &self.romaji, options)
+            .find_iter(text)
+            .collect()
+    }
+}
+
+// `text`の先頭から、各トークンの候補が区切りのほかにすき間なく順に並んでいるかどうか
+fn phrase_matches(matchers: &[CandidateMatcher], text: &str, separator: Separator) -> bool {
+    match matchers.split_first() {
+        None => true,
+        // 短い候補で一致させると後続のトークンが一致しない場合があるので、すべての長さを試す
+        Some((first, rest)) => first.prefix_lengths(text, separator).into_iter().rev().any(|len| {
+            let text = &text[len..];
+            let text = if rest.is_empty() {
+                text
+            } else {
+                &text[separator.len(text)..]
+            };
+            phrase_matches(rest, text, separator)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migemo::compact_dictionary_builder::{BuildOptions, build, build_with_options};
+    use crate::migemo::dictionary_header::{DictionaryHeader, HEADER_SIZE};
+    use crate::migemo::test_support::dict_entries;
+
+    fn dictionary_bytes() -> Vec<u8> {
+        build(dict_entries(&[("けんさく", &["検索"]), ("じしょ", &["辞書"])]))
+    }
+
+    #[test]
+    fn migemo_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Migemo<'static>>();
+    }
+
+    #[test]
+    fn builds_from_bytes_and_queries() {
+        let migemo = Migemo::builder()
+            .dictionary_bytes(dictionary_bytes())
+            .options(QueryOptions::new().full_width(false).half_width_katakana(false))
+            .build()
+            .unwrap();
+        assert!(migemo.query("kensaku").contains("検索"));
+        let texts: Vec<String> = migemo.candidates("jisho").into_iter().map(|c| c.text).collect();
+        assert!(texts.contains(&"辞書".to_string()));
+        assert!(texts.contains(&"ジショ".to_string()));
+        let found: Vec<String> = migemo
+            .find_iter("辞書で検索", "kensaku")
+            .into_iter()
+            .map(|m| m.candidate.text)
+            .collect();
+        assert_eq!(found, vec!["検索".to_string()]);
+    }
+
+    #[test]
+    fn is_match_requires_tokens_in_order() {
+        let migemo = Migemo::builder().dictionary_bytes(dictionary_bytes()).build().unwrap();
+        assert!(migemo.is_match("全文検索辞書の話", "kensaku jisho"));
+        assert!(!migemo.is_match("辞書検索の話", "kensaku jisho"));
+        assert!(!migemo.is_match("検索の辞書", "kensaku jisho"));
+        assert!(!migemo.is_match("検索", ""));
+    }
+
+    #[test]
+    fn is_match_follows_query_separators() {
+        let build = |operator: RegexOperator, options: QueryOptions| {
+            Migemo::builder()
+                .dictionary_bytes(dictionary_bytes())
+                .operator(operator)
+                .options(options)
+                .build()
+                .unwrap()
+        };
+        // 改行にマッチしない方言では、区切りを許さない
+        let migemo = build(RegexOperator::Default, QueryOptions::new());
+        assert!(!migemo.is_match("検\n索", "kensaku"));
+        let migemo = build(RegexOperator::Pcre, QueryOptions::new());
+        assert!(migemo.is_match("全文検\r\n索の話", "kensaku"));
+        assert!(migemo.is_match("検索\n辞書", "kensaku jisho"));
+        assert!(!migemo.is_match("検 索", "kensaku"));
+        assert!(!migemo.is_match("検\n\n索", "kensaku"));
+        let migemo = build(RegexOperator::Pcre, QueryOptions::new().whitespace(true));
+        assert!(migemo.is_match("け ん\n\tさく", "kensaku"));
+        assert!(migemo.is_match("検索  辞書", "kensaku jisho"));
+        // Vimの改行は空白も含む
+        let migemo = build(RegexOperator::Vim, QueryOptions::new());
+        assert!(migemo.is_match("検 索", "kensaku"));
+    }
+
+    #[test]
+    fn builds_from_borrowed_bytes_and_files() {
+        let bytes = dictionary_bytes();
+        let migemo = Migemo::builder().dictionary_ref(&bytes).build().unwrap();
+        assert!(migemo.is_match("全文検索の話", "kensaku"));
+
+        let path = std::env::temp_dir().join(format!("rustmigemo-facade-{}.dict", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        let migemo = Migemo::builder().dictionary_path(&path).build().unwrap();
+        assert!(migemo.is_match("辞書を引く", "jisho"));
+        drop(migemo);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn build_reports_dictionary_errors() {
+        let error = Migemo::builder().dictionary_bytes(vec![1, 2, 3]).build().err().unwrap();
+        assert!(matches!(error, MigemoError::Dictionary { path: None, .. }));
+        // CRC-32が合っていても、壊れたマッピングを持つ辞書は読み込まない
+        let options = BuildOptions {
+            with_header: true,
+            ..BuildOptions::default()
+        };
+        let mut payload =
+            build_with_options(dict_entries(&[("けんさく", &["検索"])]), &options)[HEADER_SIZE..].to_vec();
+        let last = payload.len() - 4;
+        payload[last..].copy_from_slice(&[0, 0, 0, 0]);
+        let mut forged = Vec::new();
+        DictionaryHeader::for_payload(&payload, 0).write_to(&mut forged);
+        forged.extend_from_slice(&payload);
+        let error = Migemo::builder().dictionary_ref(&forged).build().err().unwrap();
+        assert!(matches!(error, MigemoError::Dictionary { path: None, .. }));
+        let error = Migemo::builder()
+            .dictionary_path("no-such-migemo-dict")
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(error, MigemoError::Io { .. }));
+    }
+}
@@ -1,14 +1,16 @@
+use super::array_view::ArrayView;
 use super::bit_vector::BitVector;
+use std::ops::Range;
 
 #[derive(Debug)]
-pub struct LoudsTriePredictiveSearchIter<'a> {
-    trie: &'a LoudsTrie,
+pub struct LoudsTriePredictiveSearchIter<'a, W = Vec<u64>, E = Vec<u16>> {
+    trie: &'a LoudsTrie<W, E>,
     upper: usize,
     lower: usize,
     cursor: usize,
 }
 
-impl<'a> Iterator for LoudsTriePredictiveSearchIter<'a> {
+impl<'a, W: ArrayView<u64>, E: ArrayView<u16>> Iterator for LoudsTriePredictiveSearchIter<'a, W, E> {
     type Item = usize;
     fn next(&mut self) -> Option<usize> {
         if self.cursor < self.upper {
@@ -19,7 +21,12 @@ impl<'a> Iterator for LoudsTriePredictiveSearchIter<'a> {
             return None;
         } else {
             let lower_select = self.trie.bit_vector.select(self.lower, false);
-            let upper_select = self.trie.bit_vector.select(self.upper, false);
+            // 最後のノードの次を指している場合は、対応する0が存在しないので末尾として扱う
+            let upper_select = if self.upper < self.trie.edges.len() {
+                self.trie.bit_vector.select(self.upper, false)
+            } else {
+                self.trie.bit_vector.size()
+            };
 
             if lower_select + 1 <= self.trie.bit_vector.size() {
                 self.lower = self.trie.bit_vector.rank(lower_select + 1, true) + 1;
@@ -33,7 +40,7 @@ impl<'a> Iterator for LoudsTriePredictiveSearchIter<'a> {
                 self.upper = self.trie.bit_vector.rank(self.trie.bit_vector.size(), true) + 1;
             }
 
-            self.cursor = self.lower;
+            self.cursor = self.lower + 1;
             if self.lower == self.upper {
                 return None;
             } else {
@@ -44,15 +51,15 @@ impl<'a> Iterator for LoudsTriePredictiveSearchIter<'a> {
 }
 
 #[derive(Debug)]
-pub struct LoudsTrieCommonPrefixSearchIter<'a, 'b> {
-    trie: &'a LoudsTrie,
+pub struct LoudsTrieCommonPrefixSearchIter<'a, 'b, W = Vec<u64>, E = Vec<u16>> {
+    trie: &'a LoudsTrie<W, E>,
     key: &'b [u16],
     node_index: usize,
     position: usize,
     finished: bool,
 }
 
-impl<'a, 'b> Iterator for LoudsTrieCommonPrefixSearchIter<'a, 'b> {
+impl<'a, 'b, W: ArrayView<u64>, E: ArrayView<u16>> Iterator for LoudsTrieCommonPrefixSearchIter<'a, 'b, W, E> {
     type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -77,13 +84,17 @@ impl<'a, 'b> Iterator for LoudsTrieCommonPrefixSearchIter<'a, 'b> {
     }
 }
 
+/// LOUDSで表現したトライ木
+///
+/// `W`はビットベクトルのワード、`E`はエッジのラベルを格納する配列で、
+/// 既定では`Vec`だが、辞書ファイルのバイト列を直接参照することもできる。
 #[derive(Debug)]
-pub struct LoudsTrie {
-    pub bit_vector: BitVector,
-    pub edges: Vec<u16>,
+pub struct LoudsTrie<W = Vec<u64>, E = Vec<u16>> {
+    pub bit_vector: BitVector<W>,
+    pub edges: E,
 }
 
-impl LoudsTrie {
+impl<W: ArrayView<u64>, E: ArrayView<u16>> LoudsTrie<W, E> {
     pub fn get_key(&self, mut index: usize) -> Vec<u16> {
         assert!(
             index > 0 && index < self.edges.len(),
@@ -93,7 +104,7 @@ impl LoudsTrie {
         );
         let mut sb: Vec<u16> = Vec::new();
         while index > 1 {
-            sb.push(self.edges[index]);
+            sb.push(self.edges.at(index));
             index = self.parent(index);
         }
         sb.reverse();
@@ -109,7 +120,7 @@ impl LoudsTrie {
         );
         target.clear();
         while index > 1 {
-            target.push(self.edges[index]);
+            target.push(self.edges.at(index));
             index = self.parent(index);
         }
         target.reverse();
@@ -122,29 +133,42 @@ impl LoudsTrie {
 
     pub fn first_child(&self, x: usize) -> Option<usize> {
         let y = self.bit_vector.select(x, false) + 1;
-        if self.bit_vector.get(y) {
+        if y < self.bit_vector.size() && self.bit_vector.get(y) {
             return Some(self.bit_vector.rank(y, true) + 1);
         } else {
             return None;
         }
     }
 
-    pub fn traverse(&self, index: u32, c: u16) -> Option<usize> {
-        let first_child = self.first_child(index as usize);
-        if first_child.is_none() {
-            return None;
+    // 子ノードの番号の範囲を返す
+    fn children(&self, x: usize) -> Range<usize> {
+        match self.first_child(x) {
+            Some(first_child) => {
+                let child_start_bit = self.bit_vector.select(first_child, true);
+                let child_end_bit = self.bit_vector.next_clear_bit(child_start_bit);
+                first_child..first_child + (child_end_bit - child_start_bit)
+            }
+            None => 0..0,
         }
-        let first_child = first_child.unwrap();
-        let child_start_bit = self.bit_vector.select(first_child as usize, true);
-        let child_end_bit = self.bit_vector.next_clear_bit(child_start_bit);
-        let child_size = child_end_bit - child_start_bit;
-        let from = first_child as usize;
-        let to = from + child_size;
-        if let Ok(idx) = self.edges[from..to].binary_search(&c) {
-            Some(from + idx)
-        } else {
-            None
+    }
+
+    pub fn traverse(&self, index: u32, c: u16) -> Option<usize> {
+        let children = self.children(index as usize);
+        // 兄弟ノードのエッジはソートされているので、二分探索で子ノードを探す
+        let mut from = children.start;
+        let mut to = children.end;
+        while from < to {
+            let mid = from + (to - from) / 2;
+            let edge = self.edges.at(mid);
+            if edge < c {
+                from = mid + 1;
+            } else if edge > c {
+                to = mid;
+            } else {
+                return Some(mid);
+            }
         }
+        None
     }
 
     pub fn get(&self, key: &[u16]) -> Option<usize> {
@@ -160,7 +184,7 @@ impl LoudsTrie {
         return Some(node_index);
     }
 
-    pub fn predictive_search(&self, node: usize) -> LoudsTriePredictiveSearchIter<'_> {
+    pub fn predictive_search(&self, node: usize) -> LoudsTriePredictiveSearchIter<'_, W, E> {
         let lower = node;
         let upper = node + 1;
         return LoudsTriePredictiveSearchIter {
@@ -171,7 +195,46 @@ impl LoudsTrie {
         };
     }
 
-    pub fn common_prefix_search<'a, 'b>(&'a self, key: &'b [u16]) -> LoudsTrieCommonPrefixSearchIter<'a, 'b> {
+    /// `key`との編集距離 (挿入・削除・置換の回数) が`max_edits`以下のキーを持つノードを、編集距離とともに返す
+    ///
+    /// 根から子をたどりながら編集距離の表を1行ずつ計算し、行の最小値が`max_edits`を超えた枝は打ち切る。
+    /// ノードは深さ優先の順に並び、根は含まない。
+    pub fn fuzzy_search(&self, key: &[u16], max_edits: usize) -> Vec<(usize, usize)> {
+        let mut results = Vec::new();
+        let row: Vec<usize> = (0..=key.len()).collect();
+        for child in self.children(1) {
+            self.fuzzy_search_from(child, key, max_edits, &row, &mut results);
+        }
+        results
+    }
+
+    // `node`のエッジの分だけ編集距離の表を進め、一致すれば記録して子をたどる
+    fn fuzzy_search_from(
+        &self,
+        node: usize,
+        key: &[u16],
+        max_edits: usize,
+        parent_row: &[usize],
+        results: &mut Vec<(usize, usize)>,
+    ) {
+        let c = self.edges.at(node);
+        let mut row = Vec::with_capacity(parent_row.len());
+        row.push(parent_row[0] + 1);
+        for (i, &k) in key.iter().enumerate() {
+            let substitution = parent_row[i] + usize::from(k != c);
+            row.push(substitution.min(parent_row[i + 1] + 1).min(row[i] + 1));
+        }
+        if row[key.len()] <= max_edits {
+            results.push((node, row[key.len()]));
+        }
+        if row.iter().min().is_some_and(|&min| min <= max_edits) {
+            for child in self.children(node) {
+                self.fuzzy_search_from(child, key, max_edits, &row, results);
+            }
+        }
+    }
+
+    pub fn common_prefix_search<'a, 'b>(&'a self, key: &'b [u16]) -> LoudsTrieCommonPrefixSearchIter<'a, 'b, W, E> {
         LoudsTrieCommonPrefixSearchIter {
             trie: self,
             key,
@@ -185,6 +248,40 @@ impl LoudsTrie {
         return self.edges.len() - 2;
     }
 
+    /// ビット列が木構造として正しいかを検査する。
+    /// 子ノードの番号は必ず親ノードの番号より大きいので、`parent`を辿るループが停止することも保証される。
+    pub fn is_well_formed(&self) -> bool {
+        let size = self.bit_vector.size();
+        if self.edges.len() < 2 || size < 2 || !self.bit_vector.get(0) || self.bit_vector.get(1) {
+            return false;
+        }
+        let num_of_nodes = self.edges.len() - 1;
+        let mut ones = 1;
+        let mut zeros = 1;
+        for pos in 2..size {
+            if self.bit_vector.get(pos) {
+                ones += 1;
+                // ones番目のノードの親は、それまでに現れた0の数で表される
+                if ones > num_of_nodes || zeros >= ones {
+                    return false;
+                }
+            } else {
+                zeros += 1;
+            }
+        }
+        ones == num_of_nodes && zeros == num_of_nodes
+    }
+
+    /// ビット列とエッジを`Vec`にコピーした、所有権を持つトライに変換する
+    pub fn into_owned(self) -> LoudsTrie {
+        LoudsTrie {
+            bit_vector: self.bit_vector.into_owned(),
+            edges: self.edges.to_vec(),
+        }
+    }
+}
+
+impl LoudsTrie {
     pub fn build(keys: &Vec<Vec<u16>>) -> (LoudsTrie, Vec<u32>) {
         let mut memo: Vec<i32> = vec![1; keys.len()];
         let mut offset = 0;
@@ -282,6 +379,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_beyond_last_node() {
+        let words: Vec<Vec<u16>> = vec!["baby", "bad", "bank", "box", "dad", "dance"]
+            .iter()
+            .map(|x| x.encode_utf16().collect())
+            .collect();
+        let (trie, _) = LoudsTrie::build(&words);
+        assert!(trie.is_well_formed());
+        let dancer: Vec<u16> = "dancer".encode_utf16().collect();
+        assert_eq!(trie.get(&dancer), None);
+    }
+
+    #[test]
+    fn test_predictive_search_reaches_last_node() {
+        let words: Vec<Vec<u16>> = vec!["baby", "bad", "bank", "box", "dad", "dance"]
+            .iter()
+            .map(|x| x.encode_utf16().collect())
+            .collect();
+        let (trie, _) = LoudsTrie::build(&words);
+        let d: Vec<u16> = "d".encode_utf16().collect();
+        let node = trie.get(&d).unwrap();
+        let keys: Vec<String> = trie
+            .predictive_search(node)
+            .map(|i| String::from_utf16(&trie.get_key(i)).unwrap())
+            .collect();
+        assert_eq!(keys, vec!["d", "da", "dad", "dan", "danc", "dance"]);
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let words: Vec<Vec<u16>> = vec!["baby", "bad", "bank", "box", "dad", "dance"]
+            .iter()
+            .map(|x| x.encode_utf16().collect())
+            .collect();
+        let (trie, _) = LoudsTrie::build(&words);
+        let search = |key: &str, max_edits: usize| -> Vec<(String, usize)> {
+            let key: Vec<u16> = key.encode_utf16().collect();
+            let mut found: Vec<(String, usize)> = trie
+                .fuzzy_search(&key, max_edits)
+                .into_iter()
+                .map(|(node, distance)| (String::from_utf16(&trie.get_key(node)).unwrap(), distance))
+                .collect();
+            found.sort();
+            found
+        };
+        assert_eq!(search("bad", 0), vec![("bad".to_string(), 0)]);
+        assert_eq!(
+            search("bax", 1),
+            vec![
+                ("ba".to_string(), 1),
+                ("bab".to_string(), 1),
+                ("bad".to_string(), 1),
+                ("ban".to_string(), 1),
+                ("box".to_string(), 1)
+            ]
+        );
+        // 置換・挿入・削除をそれぞれ1回と数える
+        assert!(search("dnce", 1).contains(&("dance".to_string(), 1)));
+        assert!(search("bnak", 1).is_empty());
+        assert!(search("bnak", 2).contains(&("bank".to_string(), 2)));
+    }
+
     #[test]
     fn test_common_prefix_search() {
         let words: Vec<Vec<u16>> = vec!["a", "ab", "abc", "abcd"]
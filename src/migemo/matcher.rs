@@ -0,0 +1,283 @@
+use super::dictionary::Dictionary;
+use super::query::{Candidate, QueryOptions, query_candidates_with_options, query_candidates_with_romaji, tokenize};
+use super::regex_generator::NEWLINE;
+use super::romaji_processor::RomajiProcessor;
+use std::ops::Range;
+use std::str::CharIndices;
+
+/// テキストの中で検索語に一致した箇所
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// UTF-8のバイト単位の範囲
+    pub bytes: Range<usize>,
+    /// 文字 (Unicodeのスカラー値) 単位の範囲
+    pub chars: Range<usize>,
+    /// UTF-16のコード単位の範囲。JavaScriptの文字列の添字と一致する。
+    pub utf16: Range<usize>,
+    /// 一致した候補
+    pub candidate: Candidate,
+}
+
+/// 候補の文字の間に挟まっていてもよい区切り。生成する正規表現で文字の間に入れるパターンに対応する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Separator {
+    None,
+    /// 改行1つ
+    Newline,
+    /// 任意の長さの空白 (改行を含む)
+    Whitespace,
+}
+
+impl Separator {
+    // `RegexOperatorDetail::newline`のパターンから選ぶ
+    pub(crate) fn from_pattern(pattern: &str) -> Separator {
+        match pattern {
+            "" => Separator::None,
+            NEWLINE => Separator::Newline,
+            _ => Separator::Whitespace,
+        }
+    }
+
+    // `text`の先頭にある区切りのバイト数
+    pub(crate) fn len(self, text: &str) -> usize {
+        match self {
+            Separator::None => 0,
+            Separator::Newline if text.starts_with("\r\n") => 2,
+            Separator::Newline if text.starts_with('\n') => 1,
+            Separator::Newline => 0,
+            Separator::Whitespace => text.len() - text.trim_start().len(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    children: Vec<(char, usize)>,
+    // このノードで終わる候補の番号
+    candidate: Option<usize>,
+}
+
+/// 候補の文字列をテキストの中から探す
+///
+/// 候補を1文字ずつたどる木にまとめておき、テキストの各位置から最も長く一致する候補を探す。
+/// 正規表現エンジンを使わずに、どの候補が一致したかまで分かる。
+#[derive(Debug)]
+pub struct CandidateMatcher {
+    nodes: Vec<Node>,
+    candidates: Vec<Candidate>,
+}
+
+impl CandidateMatcher {
+    pub fn new(candidates: Vec<Candidate>) -> CandidateMatcher {
+        let mut nodes = vec![Node::default()];
+        for (index, candidate) in candidates.iter().enumerate() {
+            if candidate.text.is_empty() {
+                continue;
+            }
+            let mut node = 0;
+            for c in candidate.text.chars() {
+                node = match nodes[node].children.iter().find(|(code, _)| *code == c) {
+                    Some((_, child)) => *child,
+                    None => {
+                        nodes.push(Node::default());
+                        let child = nodes.len() - 1;
+                        nodes[node].children.push((c, child));
+                        child
+                    }
+                };
+            }
+            // 同じ文字列の候補は、先に現れたものを使う
+            nodes[node].candidate.get_or_insert(index);
+        }
+        CandidateMatcher { nodes, candidates }
+    }
+
+    /// 検索語をトークンごとに展開した候補から作る
+    ///
+    /// トークンはそれぞれ独立に探すので、いずれかのトークンに一致した箇所がすべて見つかる。
+    pub fn for_query<D: Dictionary + ?Sized>(word: &str, dict: &D, options: &QueryOptions) -> CandidateMatcher {
+        let candidates = tokenize(word)
+            .flat_map(|token| query_candidates_with_options(token, dict, options))
+            .collect();
+        CandidateMatcher::new(candidates)
+    }
+
+    /// 組み込みの変換表の代わりに`romaji`を使って、検索語から作る
+    pub fn for_query_with_romaji<D: Dictionary + ?Sized>(
+        word: &str,
+        dict: &D,
+        romaji: &RomajiProcessor,
+        options: &QueryOptions,
+    ) -> CandidateMatcher {
+        let candidates = tokenize(word)
+            .flat_map(|token| query_candidates_with_romaji(token, dict, romaji, options))
+            .collect();
+        CandidateMatcher::new(candidates)
+    }
+
+    /// テキストの中で候補に一致した箇所を、先頭から順に重ならないように返す
+    pub fn find_iter<'m, 't>(&'m self, text: &'t str) -> FindIter<'m, 't> {
+        FindIter {
+            matcher: self,
+            cursor: Cursor::new(text),
+        }
+    }
+
+    // `text`の先頭から一致するすべての候補のバイト数を、短い順に返す。候補の文字の間には`separator`を許す。
+    pub(crate) fn prefix_lengths(&self, text: &str, separator: Separator) -> Vec<usize> {
+        let mut node = 0;
+        let mut position = 0;
+        let mut lengths = Vec::new();
+        while let Some(c) = text[position..].chars().next() {
+            match self.nodes[node].children.iter().find(|(code, _)| *code == c) {
+                Some((_, child)) => node = *child,
+                None => break,
+            }
+            position += c.len_utf8();
+            if self.nodes[node].candidate.is_some() {
+                lengths.push(position);
+            }
+            if self.nodes[node].children.is_empty() {
+                break;
+            }
+            position += separator.len(&text[position..]);
+        }
+        lengths
+    }
+
+    // `text`の先頭から最も長く一致する候補の番号と、そのバイト数を返す
+    fn longest_match(&self, text: &str) -> Option<(usize, usize)> {
+        let mut node = 0;
+        let mut longest = None;
+        for (i, c) in text.char_indices() {
+            match self.nodes[node].children.iter().find(|(code, _)| *code == c) {
+                Some((_, child)) => node = *child,
+                None => break,
+            }
+            if let Some(candidate) = self.nodes[node].candidate {
+                longest = Some((candidate, i + c.len_utf8()));
+            }
+        }
+        longest
+    }
+}
+
+/// `CandidateMatcher::find_iter`が返すイテレータ
+pub struct FindIter<'m, 't> {
+    matcher: &'m CandidateMatcher,
+    cursor: Cursor<'t>,
+}
+
+impl Iterator for FindIter<'_, '_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        self.cursor.next_match(self.matcher)
+    }
+}
+
+// テキストのどこまで調べたか
+struct Cursor<'t> {
+    text: &'t str,
+    chars: CharIndices<'t>,
+    // 次に調べる位置の、文字単位とUTF-16単位のオフセット
+    char_offset: usize,
+    utf16_offset: usize,
+}
+
+impl<'t> Cursor<'t> {
+    fn new(text: &'t str) -> Cursor<'t> {
+        Cursor {
+            text,
+            chars: text.char_indices(),
+            char_offset: 0,
+            utf16_offset: 0,
+        }
+    }
+
+    fn next_match(&mut self, matcher: &CandidateMatcher) -> Option<Match> {
+        while let Some((start, c)) = self.chars.next() {
+            if let Some((candidate, len)) = matcher.longest_match(&self.text[start..]) {
+                let matched = &self.text[start..start + len];
+                let char_len = matched.chars().count();
+                let utf16_len = matched.encode_utf16().count();
+                let m = Match {
+                    bytes: start..start + len,
+                    chars: self.char_offset..self.char_offset + char_len,
+                    utf16: self.utf16_offset..self.utf16_offset + utf16_len,
+                    candidate: matcher.candidates[candidate].clone(),
+                };
+                // 一致した範囲の残りの文字を読み飛ばす
+                for _ in 1..char_len {
+                    self.chars.next();
+                }
+                self.char_offset += char_len;
+                self.utf16_offset += utf16_len;
+                return Some(m);
+            }
+            self.char_offset += 1;
+            self.utf16_offset += c.len_utf16();
+        }
+        None
+    }
+}
+
+/// テキストの中で検索語に一致した箇所を、先頭から順に返す
+///
+/// 検索語はトークンごとに展開し、いずれかの候補に一致した箇所を、重ならないように最も長く一致する候補で返す。
+pub fn find_iter<'t, D: Dictionary + ?Sized>(
+    text: &'t str,
+    word: &str,
+    dict: &D,
+    options: &QueryOptions,
+) -> impl Iterator<Item = Match> + 't {
+    let matcher = CandidateMatcher::for_query(word, dict, options);
+    let mut cursor = Cursor::new(text);
+    std::iter::from_fn(move || cursor.next_match(&matcher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migemo::query::CandidateSource;
+    use crate::migemo::test_support::build_dict;
+
+    #[test]
+    fn find_iter_reports_ranges_and_candidates() {
+        let dict = build_dict(&[("けんさく", &["検索", "検索機"])]);
+        let text = "𠮷野の検索機でけんさく、kensaku";
+        let matches: Vec<Match> = find_iter(text, "kensaku", &dict, &QueryOptions::default()).collect();
+        let found: Vec<(&str, Range<usize>, Range<usize>)> = matches
+            .iter()
+            .map(|m| (&text[m.bytes.clone()], m.chars.clone(), m.utf16.clone()))
+            .collect();
+        // 「𠮷」はUTF-16ではサロゲートペアになる
+        assert_eq!(
+            found,
+            vec![
+                ("検索機", 3..6, 4..7),
+                ("けんさく", 7..11, 8..12),
+                ("kensaku", 12..19, 13..20)
+            ]
+        );
+        assert_eq!(
+            matches[0].candidate.source,
+            CandidateSource::Dictionary {
+                key: "けんさく".to_string(),
+                weight: 0
+            }
+        );
+        assert_eq!(matches[1].candidate.source, CandidateSource::Hiragana);
+        assert_eq!(matches[2].candidate.source, CandidateSource::Input);
+    }
+
+    #[test]
+    fn find_iter_searches_each_token() {
+        let dict = build_dict(&[]);
+        let text = "あいうえお かきくけこ";
+        let found: Vec<&str> = find_iter(text, "iu ku", &dict, &QueryOptions::default())
+            .map(|m| &text[m.bytes])
+            .collect();
+        assert_eq!(found, vec!["いう", "く"]);
+    }
+}
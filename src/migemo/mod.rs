@@ -1,11 +1,26 @@
+pub mod array_view;
 pub mod bit_list;
 pub mod bit_vector;
 pub mod character_converter;
 pub mod compact_dictionary;
 pub mod compact_dictionary_builder;
+pub mod dictionary;
+pub mod dictionary_header;
+pub mod facade;
 pub mod louds_trie;
+pub mod matcher;
 pub mod query;
 pub mod regex_generator;
 pub mod romaji_processor;
+pub mod romaji_scheme;
+#[cfg(feature = "regex")]
+pub mod rust_regex;
+pub mod skk_dictionary;
 pub mod sorted_array_trie;
 pub mod ternary_regex_generator;
+#[cfg(test)]
+mod test_support;
+pub mod text_dictionary;
+pub mod user_dictionary;
+
+pub use facade::{Migemo, MigemoBuilder, MigemoError};
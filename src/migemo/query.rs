@@ -1,11 +1,14 @@
 use super::character_converter::*;
-use super::compact_dictionary::*;
+use super::compact_dictionary::FuzzyMatch;
+use super::dictionary::Dictionary;
 use super::regex_generator::*;
 use super::romaji_processor::RomajiProcessor;
 use super::ternary_regex_generator::*;
 use std::char::{REPLACEMENT_CHARACTER, decode_utf16};
+use std::cmp::Reverse;
+use std::collections::HashSet;
 use std::iter::Peekable;
-use std::str::CharIndices;
+use std::str::{CharIndices, FromStr};
 use std::sync::OnceLock;
 
 fn romaji_processor() -> &'static RomajiProcessor {
@@ -13,72 +16,638 @@ fn romaji_processor() -> &'static RomajiProcessor {
     ROMAJI_PROCESSOR.get_or_init(RomajiProcessor::new)
 }
 
-pub fn query_a_word(word: &str, dict: &CompactDictionary, operator: &RegexOperator) -> String {
+/// 候補がどこから得られたか
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CandidateSource {
+    /// 入力された文字列そのもの
+    Input,
+    /// 辞書を読み`key`で前方一致検索して得られた単語と、辞書に格納された重み (出現頻度など)。
+    /// 重みを持たない辞書では、重みは0になる。
+    Dictionary { key: String, weight: u32 },
+    /// ローマ字を変換した読みと編集距離が`distance`以下の読み`key`で、辞書を引いて得られた単語と、その重み。
+    /// 入力の綴り誤りを許して見つけたもので、入力どおりの単語とは限らない。
+    Fuzzy { key: String, distance: usize, weight: u32 },
+    /// 入力を全角にしたもの
+    FullWidth,
+    /// 入力を半角にしたもの
+    HalfWidth,
+    /// ローマ字をひらがなにしたもの
+    Hiragana,
+    /// ローマ字をカタカナにしたもの
+    Katakana,
+    /// ローマ字を半角カタカナにしたもの
+    HalfWidthKatakana,
+}
+
+/// 検索語を展開して得られた候補
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub text: String,
+    pub source: CandidateSource,
+}
+
+/// 検索語をどのように展開するかの設定
+///
+/// 既定ではすべての候補を生成する。
+///
+/// ```
+/// use rustmigemo::migemo::query::QueryOptions;
+/// // コードの検索など、全角文字が現れない場面向けの設定
+/// let options = QueryOptions::new().full_width(false).max_dictionary_hits(Some(20));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryOptions {
+    /// 入力された文字列そのもの
+    pub input: bool,
+    /// 入力を全角にしたもの
+    pub full_width: bool,
+    /// 入力を半角にしたもの
+    pub half_width: bool,
+    /// ローマ字をひらがなにしたもの
+    pub hiragana: bool,
+    /// ローマ字をカタカナにしたもの
+    pub katakana: bool,
+    /// ローマ字を半角カタカナにしたもの
+    pub half_width_katakana: bool,
+    /// 辞書を引いて得られた単語
+    pub dictionary: bool,
+    /// 辞書を前方一致で引く。`false`の場合は完全一致で引く。
+    pub predictive: bool,
+    /// 1つの単語あたりの辞書の候補数の上限
+    pub max_dictionary_hits: Option<usize>,
+    /// 1つのトークンから正規表現を生成する候補の数の上限。
+    /// `Some(0)`を指定しても、最も役に立つ候補 (入力そのものなど) を少なくとも1つは残す。
+    pub max_candidates: Option<usize>,
+    /// ローマ字の区切り方を何通りまで試すか。`tenin`から「てんいん」を得るには2以上にする。
+    /// 1では、最も長く一致するつづりから順に区切った読みだけを使う。
+    pub max_romaji_readings: usize,
+    /// ローマ字を変換した読みに対して、何文字までの挿入・削除・置換を許して辞書を引くか。
+    /// 0では近似検索をしない。`kensku`から「検索」を得るには1以上にする。
+    pub max_edits: usize,
+    /// 1つのトークンから生成する正規表現の長さ (UTF-8のバイト数) の上限
+    pub max_pattern_length: Option<usize>,
+    /// 文字の間やトークンの間で、改行だけでなく任意の空白を許す。
+    /// 改行にマッチしない正規表現 (`RegexOperator::Default`や`VimNonNewline`など) では何もしない。
+    pub whitespace: bool,
+}
+
+impl Default for QueryOptions {
+    fn default() -> QueryOptions {
+        QueryOptions {
+            input: true,
+            full_width: true,
+            half_width: true,
+            hiragana: true,
+            katakana: true,
+            half_width_katakana: true,
+            dictionary: true,
+            predictive: true,
+            max_dictionary_hits: None,
+            max_candidates: None,
+            max_romaji_readings: 1,
+            max_edits: 0,
+            max_pattern_length: None,
+            whitespace: false,
+        }
+    }
+}
+
+impl QueryOptions {
+    pub fn new() -> QueryOptions {
+        QueryOptions::default()
+    }
+
+    pub fn input(mut self, enabled: bool) -> QueryOptions {
+        self.input = enabled;
+        self
+    }
+
+    pub fn full_width(mut self, enabled: bool) -> QueryOptions {
+        self.full_width = enabled;
+        self
+    }
+
+    pub fn half_width(mut self, enabled: bool) -> QueryOptions {
+        self.half_width = enabled;
+        self
+    }
+
+    pub fn hiragana(mut self, enabled: bool) -> QueryOptions {
+        self.hiragana = enabled;
+        self
+    }
+
+    pub fn katakana(mut self, enabled: bool) -> QueryOptions {
+        self.katakana = enabled;
+        self
+    }
+
+    pub fn half_width_katakana(mut self, enabled: bool) -> QueryOptions {
+        self.half_width_katakana = enabled;
+        self
+    }
+
+    pub fn dictionary(mut self, enabled: bool) -> QueryOptions {
+        self.dictionary = enabled;
+        self
+    }
+
+    pub fn predictive(mut self, enabled: bool) -> QueryOptions {
+        self.predictive = enabled;
+        self
+    }
+
+    pub fn max_dictionary_hits(mut self, max: Option<usize>) -> QueryOptions {
+        self.max_dictionary_hits = max;
+        self
+    }
+
+    pub fn max_candidates(mut self, max: Option<usize>) -> QueryOptions {
+        self.max_candidates = max;
+        self
+    }
+
+    pub fn max_romaji_readings(mut self, max: usize) -> QueryOptions {
+        self.max_romaji_readings = max;
+        self
+    }
+
+    pub fn max_edits(mut self, max: usize) -> QueryOptions {
+        self.max_edits = max;
+        self
+    }
+
+    pub fn max_pattern_length(mut self, max: Option<usize>) -> QueryOptions {
+        self.max_pattern_length = max;
+        self
+    }
+
+    pub fn whitespace(mut self, enabled: bool) -> QueryOptions {
+        self.whitespace = enabled;
+        self
+    }
+}
+
+/// 候補を、同じ文字列が重複しないように集める
+struct CandidateCollector<'a> {
+    options: &'a QueryOptions,
+    candidates: Vec<Candidate>,
+    seen: HashSet<String>,
+    dictionary_hits: usize,
+}
+
+impl CandidateCollector<'_> {
+    fn push(&mut self, text: String, source: CandidateSource) {
+        if !text.is_empty() && self.seen.insert(text.clone()) {
+            self.candidates.push(Candidate { text, source });
+        }
+    }
+
+    fn has_reached_dictionary_limit(&self) -> bool {
+        self.options
+            .max_dictionary_hits
+            .is_some_and(|max| self.dictionary_hits >= max)
+    }
+
+    fn push_dictionary_hits<D: Dictionary + ?Sized>(&mut self, dict: &D, key: &[u16]) {
+        if !self.options.dictionary {
+            return;
+        }
+        let key_string = String::from_utf16_lossy(key);
+        let mut words = if self.options.predictive {
+            dict.predictive_search_weighted(key)
+        } else {
+            dict.search_weighted(key)
+        };
+        if dict.has_weights() {
+            // 単語数の上限に達しても、よく使われる単語が残るように重みの大きい順に並べる
+            let mut sorted: Vec<(Vec<u16>, u32)> = words.collect();
+            sorted.sort_by_key(|(_, weight)| Reverse(*weight));
+            words = Box::new(sorted.into_iter());
+        }
+        for (elem, weight) in words {
+            if self.has_reached_dictionary_limit() {
+                break;
+            }
+            self.push_dictionary_word(
+                &elem,
+                CandidateSource::Dictionary {
+                    key: key_string.clone(),
+                    weight,
+                },
+            );
+        }
+    }
+
+    // 読みに完全一致・前方一致する単語はすでに集めてあるので、編集が必要だった単語だけを加える
+    fn push_fuzzy_dictionary_hits<D: Dictionary + ?Sized>(&mut self, dict: &D, key: &[u16]) {
+        if !self.options.dictionary || self.options.max_edits == 0 {
+            return;
+        }
+        // 辞書は入力に近い読みの単語から返すので、上限に達した時点でそれより遠い読みは引かない
+        let mut matches = dict
+            .fuzzy_search_weighted(key, self.options.max_edits, self.options.predictive)
+            .filter(|m| m.distance > 0)
+            .peekable();
+        while let Some(first) = matches.next() {
+            if self.has_reached_dictionary_limit() {
+                break;
+            }
+            // 同じ近さの単語の中では、重みの大きい単語を先にする
+            let distance = first.distance;
+            let mut group: Vec<FuzzyMatch> = vec![first];
+            while let Some(m) = matches.next_if(|m| m.distance == distance) {
+                group.push(m);
+            }
+            group.sort_by_key(|m| Reverse(m.weight));
+            for m in group {
+                if self.has_reached_dictionary_limit() {
+                    break;
+                }
+                self.push_dictionary_word(
+                    &m.word,
+                    CandidateSource::Fuzzy {
+                        key: String::from_utf16_lossy(&m.key),
+                        distance: m.distance,
+                        weight: m.weight,
+                    },
+                );
+            }
+        }
+    }
+
+    fn push_dictionary_word(&mut self, word: &[u16], source: CandidateSource) {
+        let text: String = decode_utf16(word.iter().cloned())
+            .map(|r| r.unwrap_or(REPLACEMENT_CHARACTER))
+            .collect();
+        let len = self.candidates.len();
+        self.push(text, source);
+        if self.candidates.len() > len {
+            self.dictionary_hits += 1;
+        }
+    }
+}
+
+/// 1つの単語を展開し、候補とその出どころを返す
+///
+/// 同じ文字列の候補は、最初に得られたものだけを返す。
+/// 辞書の単語は、読みごとに重みの大きい順に並ぶ。
+pub fn query_candidates<D: Dictionary + ?Sized>(word: &str, dict: &D) -> Vec<Candidate> {
+    query_candidates_with_options(word, dict, &QueryOptions::default())
+}
+
+/// 設定に従って1つの単語を展開し、候補とその出どころを返す
+pub fn query_candidates_with_options<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+    options: &QueryOptions,
+) -> Vec<Candidate> {
+    query_candidates_with_romaji(word, dict, romaji_processor(), options)
+}
+
+/// 組み込みの変換表の代わりに`romaji`を使って、1つの単語を展開する
+pub fn query_candidates_with_romaji<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+    romaji: &RomajiProcessor,
+    options: &QueryOptions,
+) -> Vec<Candidate> {
+    let mut collector = CandidateCollector {
+        options,
+        candidates: Vec::new(),
+        seen: HashSet::new(),
+        dictionary_hits: 0,
+    };
+    if options.input {
+        collector.push(word.to_string(), CandidateSource::Input);
+    }
+
+    let lower: Vec<u16> = word.to_lowercase().encode_utf16().collect();
+    collector.push_dictionary_hits(dict, &lower);
+
+    if options.full_width {
+        collector.push(han2zen(word.to_string()), CandidateSource::FullWidth);
+    }
+    if options.half_width {
+        collector.push(zen2han(word.to_string()), CandidateSource::HalfWidth);
+    }
+
+    let readings = if options.max_romaji_readings > 1 {
+        romaji.romaji_to_hiragana_segmentations(&lower, options.max_romaji_readings)
+    } else {
+        vec![romaji.romaji_to_hiragana_predictively(&lower)]
+    };
+    let hiragana: Vec<Vec<u16>> = readings
+        .into_iter()
+        .flat_map(|reading| {
+            reading.suffixes.into_iter().map(move |suffix| {
+                let mut hira = reading.prefix.clone();
+                hira.extend(suffix);
+                hira
+            })
+        })
+        .collect();
+    for hira in &hiragana {
+        if options.hiragana {
+            let hira_string: String = decode_utf16(hira.iter().cloned())
+                .map(|r| r.unwrap_or(REPLACEMENT_CHARACTER))
+                .collect();
+            collector.push(hira_string, CandidateSource::Hiragana);
+        }
+
+        collector.push_dictionary_hits(dict, hira);
+
+        if options.katakana || options.half_width_katakana {
+            let kata = hira2kata(&String::from_utf16_lossy(hira));
+            if options.katakana {
+                collector.push(kata.clone(), CandidateSource::Katakana);
+            }
+            if options.half_width_katakana {
+                collector.push(zen2han(kata), CandidateSource::HalfWidthKatakana);
+            }
+        }
+    }
+    // 綴り誤りを許した単語は、どの読みで引いた単語よりも後に置く
+    for hira in &hiragana {
+        collector.push_fuzzy_dictionary_hits(dict, hira);
+    }
+    collector.candidates
+}
+
+/// 候補から正規表現を生成する
+pub fn generate_regex<T: RegexGeneratorTrait>(
+    candidates: &[Candidate],
+    operator: &RegexOperator,
+    generator: &mut T,
+) -> String {
+    for candidate in candidates {
+        let chars: Vec<char> = candidate.text.chars().collect();
+        generator.add(&chars);
+    }
+    generator.generate(operator)
+}
+
+pub fn query_a_word<D: Dictionary + ?Sized>(word: &str, dict: &D, operator: &RegexOperator) -> String {
     query_a_word_with_generator(word, dict, operator, &mut TernaryRegexGenerator::new())
 }
 
-pub fn query_a_word_with_generator<T: RegexGeneratorTrait>(
+pub fn query_a_word_with_generator<D: Dictionary + ?Sized, T: RegexGeneratorTrait>(
     word: &str,
-    dict: &CompactDictionary,
+    dict: &D,
     operator: &RegexOperator,
     generator: &mut T,
 ) -> String {
-    let word_chars: Vec<char> = word.chars().collect();
-    generator.add(&word_chars);
+    query_a_word_with_generator_and_options(word, dict, operator, &QueryOptions::default(), generator)
+}
 
-    let lower: Vec<u16> = word.to_lowercase().encode_utf16().collect();
-    for elem in dict.predictive_search(&lower) {
-        let elem_chars: Vec<char> = decode_utf16(elem.iter().cloned())
-            .map(|r| r.unwrap_or(REPLACEMENT_CHARACTER))
-            .collect();
-        generator.add(&elem_chars);
+/// 設定に従って候補を生成し、`generator`で1つの単語から正規表現を生成する
+pub fn query_a_word_with_generator_and_options<D: Dictionary + ?Sized, T: RegexGeneratorTrait>(
+    word: &str,
+    dict: &D,
+    operator: &RegexOperator,
+    options: &QueryOptions,
+    generator: &mut T,
+) -> String {
+    let candidates = query_candidates_with_options(word, dict, options);
+    generate_regex(&candidates, operator, generator)
+}
+
+pub fn query<D: Dictionary + ?Sized>(word: String, dict: &D, operator: &RegexOperator) -> String {
+    query_with_options(word, dict, operator, &QueryOptions::default())
+}
+
+/// 設定に従って候補を生成し、クエリ文字列から正規表現を生成する
+pub fn query_with_options<D: Dictionary + ?Sized>(
+    word: String,
+    dict: &D,
+    operator: &RegexOperator,
+    options: &QueryOptions,
+) -> String {
+    let detail = operator_detail(operator, options);
+    // トークンの境目で折り返されていてもマッチするように、トークンの間にも改行を許す
+    token_patterns(&word, dict, &detail, options).join(&detail.newline)
+}
+
+/// 複数のトークンからなる検索語を、どのように検索するか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchMode {
+    /// すべてのトークンが、この順に並んでいるものにマッチする
+    #[default]
+    Phrase,
+    /// いずれかのトークンを含むものにマッチする
+    Any,
+    /// すべてのトークンを、順序を問わず含むものにマッチする
+    All,
+}
+
+impl FromStr for SearchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SearchMode, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "phrase" => Ok(SearchMode::Phrase),
+            "any" | "or" => Ok(SearchMode::Any),
+            "all" | "and" => Ok(SearchMode::All),
+            _ => Err(format!("unsupported search mode: {}", s)),
+        }
     }
+}
 
-    let zen_str = han2zen(word.to_string());
-    let zen_chars: Vec<char> = zen_str.chars().collect();
-    generator.add(&zen_chars);
+/// クエリ文字列をトークンに分け、トークンごとの正規表現を返す
+pub fn query_tokens<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+    operator: &RegexOperator,
+    options: &QueryOptions,
+) -> Vec<String> {
+    token_patterns(word, dict, &operator_detail(operator, options), options)
+}
 
-    let han_str = zen2han(word.to_string());
-    let han_chars: Vec<char> = han_str.chars().collect();
-    generator.add(&han_chars);
+/// トークンごとの正規表現を、いずれか1つにマッチする正規表現にまとめる
+pub fn union_regex(patterns: &[String], operator: &RegexOperator) -> String {
+    if patterns.len() < 2 {
+        return patterns.concat();
+    }
+    let detail = RegexOperatorDetail::get_regex_operator_detail(operator);
+    format!(
+        "{}{}{}",
+        detail.begin_group,
+        patterns.join(&detail.or),
+        detail.end_group
+    )
+}
 
-    let hiragana = romaji_processor().romaji_to_hiragana_predictively(&lower);
-    for suffix in hiragana.suffixes {
-        let mut hira = hiragana.prefix.clone();
-        hira.extend(suffix);
-        let hira_chars: Vec<char> = decode_utf16(hira.iter().cloned())
-            .map(|r| r.unwrap_or(REPLACEMENT_CHARACTER))
-            .collect();
-        generator.add(&hira_chars);
+/// 検索の方法に従って、クエリ文字列から正規表現の一覧を生成する
+///
+/// 返す正規表現のすべてにマッチするものが、検索結果になる。
+/// `SearchMode::All`ではトークンごとの正規表現を、それ以外では1つの正規表現を返す。
+pub fn query_with_mode<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+    operator: &RegexOperator,
+    options: &QueryOptions,
+    mode: SearchMode,
+) -> Vec<String> {
+    query_with_report(word, dict, operator, options, mode).patterns
+}
 
-        for elem in dict.predictive_search(&hira) {
-            let elem_chars: Vec<char> = decode_utf16(elem.iter().cloned())
-                .map(|r| r.unwrap_or(REPLACEMENT_CHARACTER))
-                .collect();
-            generator.add(&elem_chars);
+/// 正規表現の生成結果と、上限に収めるために候補を減らしたかどうかの記録
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryReport {
+    /// `query_with_mode`と同じ正規表現の一覧
+    pub patterns: Vec<String>,
+    /// `max_candidates`や`max_pattern_length`に収めるために、取り除いた候補の数
+    pub dropped_candidates: usize,
+}
+
+impl QueryReport {
+    /// 候補を減らしたかどうか
+    pub fn is_truncated(&self) -> bool {
+        self.dropped_candidates > 0
+    }
+}
+
+/// `query_with_mode`と同じ正規表現を生成し、候補を減らしたかどうかも返す
+pub fn query_with_report<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+    operator: &RegexOperator,
+    options: &QueryOptions,
+    mode: SearchMode,
+) -> QueryReport {
+    query_with_romaji(word, dict, romaji_processor(), operator, options, mode)
+}
+
+/// 組み込みの変換表の代わりに`romaji`を使って、`query_with_report`と同じ結果を返す
+pub fn query_with_romaji<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+    romaji: &RomajiProcessor,
+    operator: &RegexOperator,
+    options: &QueryOptions,
+    mode: SearchMode,
+) -> QueryReport {
+    let detail = operator_detail(operator, options);
+    let (patterns, dropped_candidates) = generate_token_patterns(word, dict, romaji, &detail, options);
+    let patterns = if patterns.is_empty() {
+        patterns
+    } else {
+        match mode {
+            SearchMode::Phrase => vec![patterns.join(&detail.newline)],
+            SearchMode::Any => vec![union_regex(&patterns, operator)],
+            SearchMode::All => patterns,
         }
+    };
+    QueryReport {
+        patterns,
+        dropped_candidates,
+    }
+}
 
-        let kata = hira2kata(&String::from_utf16_lossy(&hira));
-        let kata_chars: Vec<char> = kata.chars().collect();
-        generator.add(&kata_chars);
+pub(crate) fn operator_detail(operator: &RegexOperator, options: &QueryOptions) -> RegexOperatorDetail {
+    let mut detail = RegexOperatorDetail::get_regex_operator_detail(operator);
+    if options.whitespace && !detail.newline.is_empty() {
+        detail.newline = detail.whitespace.clone();
+    }
+    detail
+}
 
-        let zen_kata = zen2han(kata);
-        let zen_kata_chars: Vec<char> = zen_kata.chars().collect();
-        generator.add(&zen_kata_chars);
+fn token_patterns<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+    detail: &RegexOperatorDetail,
+    options: &QueryOptions,
+) -> Vec<String> {
+    generate_token_patterns(word, dict, romaji_processor(), detail, options).0
+}
+
+// トークンごとの正規表現と、上限に収めるために取り除いた候補の数を返す
+fn generate_token_patterns<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+    romaji: &RomajiProcessor,
+    detail: &RegexOperatorDetail,
+    options: &QueryOptions,
+) -> (Vec<String>, usize) {
+    let mut patterns = Vec::new();
+    let mut dropped = 0;
+    for w in tokenize(word) {
+        let candidates = query_candidates_with_romaji(w, dict, romaji, options);
+        let (pattern, dropped_in_token) = generate_within_budget(candidates, detail, options);
+        dropped += dropped_in_token;
+        if !pattern.is_empty() {
+            patterns.push(pattern);
+        }
     }
-    let generated = generator.generate(&operator);
-    return generated;
+    (patterns, dropped)
 }
 
-pub fn query(word: String, dict: &CompactDictionary, operator: &RegexOperator) -> String {
-    if word.is_empty() {
-        return "".to_string();
+fn generate_pattern(candidates: &[Candidate], detail: &RegexOperatorDetail) -> String {
+    let mut generator = TernaryRegexGenerator::new();
+    for candidate in candidates {
+        let chars: Vec<char> = candidate.text.chars().collect();
+        generator.add(&chars);
     }
-    let mut result = String::new();
-    for w in tokenize(&word) {
-        result.push_str(&query_a_word(w, dict, operator));
+    generator.generate_with_detail(detail)
+}
+
+/// 候補を、残すべきものから順に並べ替える
+///
+/// 入力そのもの、入力を変換した候補、辞書の単語、綴り誤りを許して引いた単語の順に並べる。
+/// 辞書の単語は重みの大きいものを先にし、それ以外は短いものを先にする。補完の候補を示す場合や、候補を減らす場合には先頭から使う。
+pub fn rank_candidates(candidates: &mut [Candidate]) {
+    candidates.sort_by(|a, b| {
+        let key = |c: &Candidate| {
+            let (rank, weight) = match c.source {
+                CandidateSource::Input => (0, 0),
+                CandidateSource::Dictionary { weight, .. } => (2, weight),
+                CandidateSource::Fuzzy { weight, .. } => (3, weight),
+                _ => (1, 0),
+            };
+            (rank, Reverse(weight), c.text.chars().count())
+        };
+        key(a).cmp(&key(b)).then_with(|| a.text.cmp(&b.text))
+    });
+}
+
+// 候補の数と正規表現の長さの上限に収まるように、役に立ちにくい候補から取り除いて正規表現を生成する
+fn generate_within_budget(
+    mut candidates: Vec<Candidate>,
+    detail: &RegexOperatorDetail,
+    options: &QueryOptions,
+) -> (String, usize) {
+    let total = candidates.len();
+    let mut keep = options.max_candidates.map_or(total, |max| total.min(max.max(1)));
+    let mut pattern = String::new();
+    if keep == total {
+        pattern = generate_pattern(&candidates, detail);
+        if options.max_pattern_length.is_none_or(|max| pattern.len() <= max) {
+            return (pattern, 0);
+        }
+    }
+
+    rank_candidates(&mut candidates);
+    if keep < total {
+        pattern = generate_pattern(&candidates[..keep], detail);
     }
-    result
+    if let Some(max) = options.max_pattern_length
+        && pattern.len() > max
+    {
+        // 上限に収まる候補の数を二分探索で求める。1つも収まらなくても、最初の候補は残す。
+        let (mut low, mut high) = (1, keep - 1);
+        keep = 1;
+        while low <= high {
+            let middle = (low + high) / 2;
+            if generate_pattern(&candidates[..middle], detail).len() <= max {
+                keep = middle;
+                low = middle + 1;
+            } else {
+                high = middle - 1;
+            }
+        }
+        pattern = generate_pattern(&candidates[..keep], detail);
+    }
+    (pattern, total - keep)
 }
 
 /// クエリ文字列をトークンに分割するイテレータ
@@ -158,6 +727,10 @@ pub fn tokenize(input: &str) -> TokenizeIter<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::migemo::compact_dictionary::CompactDictionary;
+    use crate::migemo::compact_dictionary_builder::{BuildOptions, build_with_options};
+    use crate::migemo::test_support::{build_dict, dict_entries};
+    use std::collections::HashMap;
 
     fn generate_with_both_generators(words: &[&str], op: &RegexOperator) -> (String, String) {
         let mut gen1 = RegexGenerator { root: None };
@@ -256,9 +829,296 @@ mod tests {
             begin_class: "[".to_string(),
             end_class: "]".to_string(),
             newline: "<NL>".to_string(),
+            escape: "()[]|".to_string(),
+            class_escape: "]".to_string(),
         };
         let (user1, user2) = generate_with_both_generators(&["bad", "bat"], &user_op);
         assert_eq!(user1, user2);
         assert!(user2.contains("<NL>"));
+
+        // 記号のエスケープも、両ジェネレータで一致すること
+        let words = ["a.b", "a+c", "a(d", "[", "]", "-"];
+        for op in [
+            RegexOperator::Default,
+            RegexOperator::Vim,
+            RegexOperator::Emacs,
+            RegexOperator::PosixEre,
+            user_op,
+        ] {
+            let (gen1, gen2) = generate_with_both_generators(&words, &op);
+            assert_eq!(gen1, gen2);
+        }
+    }
+
+    #[test]
+    fn test_query_candidates_reports_sources() {
+        let dict = build_dict(&[("けんさく", &["検索", "研削"])]);
+
+        let candidates = query_candidates("kensaku", &dict);
+        let find = |text: &str| candidates.iter().find(|c| c.text == text).map(|c| c.source.clone());
+        assert_eq!(candidates[0].text, "kensaku");
+        assert_eq!(find("kensaku"), Some(CandidateSource::Input));
+        assert_eq!(find("ｋｅｎｓａｋｕ"), Some(CandidateSource::FullWidth));
+        assert_eq!(find("けんさく"), Some(CandidateSource::Hiragana));
+        assert_eq!(find("ケンサク"), Some(CandidateSource::Katakana));
+        assert_eq!(find("ｹﾝｻｸ"), Some(CandidateSource::HalfWidthKatakana));
+        assert_eq!(
+            find("検索"),
+            Some(CandidateSource::Dictionary {
+                key: "けんさく".to_string(),
+                weight: 0
+            })
+        );
+        // 半角の入力を半角にしたものは入力と同じなので、重複して返さない
+        assert_eq!(candidates.iter().filter(|c| c.text == "kensaku").count(), 1);
+        assert!(!candidates.iter().any(|c| c.source == CandidateSource::HalfWidth));
+
+        let regex = generate_regex(&candidates, &RegexOperator::Default, &mut TernaryRegexGenerator::new());
+        assert_eq!(regex, query_a_word("kensaku", &dict, &RegexOperator::Default));
+    }
+
+    #[test]
+    fn test_query_with_options() {
+        let dict = build_dict(&[("けんさ", &["検査"]), ("けんさく", &["検索", "研削"])]);
+        let op = RegexOperator::Default;
+
+        assert_eq!(
+            query_with_options("kensaku".to_string(), &dict, &op, &QueryOptions::default()),
+            query("kensaku".to_string(), &dict, &op)
+        );
+
+        let options = QueryOptions::new()
+            .full_width(false)
+            .half_width_katakana(false)
+            .dictionary(false);
+        assert_eq!(
+            query_with_options("kensaku".to_string(), &dict, &op, &options),
+            "(kensaku|けんさく|ケンサク)"
+        );
+        let mut generator = TernaryRegexGenerator::new();
+        assert_eq!(
+            query_a_word_with_generator_and_options("kensaku", &dict, &op, &options, &mut generator),
+            "(kensaku|けんさく|ケンサク)"
+        );
+
+        let texts = |options: &QueryOptions| -> Vec<String> {
+            query_candidates_with_options("kensa", &dict, options)
+                .into_iter()
+                .filter(|c| matches!(c.source, CandidateSource::Dictionary { .. }))
+                .map(|c| c.text)
+                .collect()
+        };
+        assert_eq!(texts(&QueryOptions::new()).len(), 3);
+        assert_eq!(texts(&QueryOptions::new().predictive(false)), vec!["検査"]);
+        assert_eq!(texts(&QueryOptions::new().max_dictionary_hits(Some(2))).len(), 2);
+    }
+
+    #[test]
+    fn test_query_allows_newline_between_tokens() {
+        let dict = build_dict(&[]);
+        let q = |word: &str, op: &RegexOperator, options: &QueryOptions| {
+            query_with_options(word.to_string(), &dict, op, options)
+        };
+        let default = QueryOptions::default();
+
+        let op = RegexOperator::Pcre;
+        assert_eq!(
+            q("aI", &op, &default),
+            format!("{}(?:\\r?\\n)?{}", q("a", &op, &default), q("I", &op, &default))
+        );
+        let whitespace = QueryOptions::new().whitespace(true);
+        assert_eq!(q("ka", &op, &whitespace), "(?:[かカｶ]|k\\s*a|ｋ\\s*ａ)");
+        assert_eq!(
+            q("aI", &op, &whitespace),
+            format!("{}\\s*{}", q("a", &op, &whitespace), q("I", &op, &whitespace))
+        );
+
+        // 改行にマッチしない正規表現では、トークンをそのままつなげる
+        let op = RegexOperator::PcreNonNewline;
+        assert_eq!(
+            q("aI", &op, &whitespace),
+            format!("{}{}", q("a", &op, &default), q("I", &op, &default))
+        );
+    }
+
+    #[test]
+    fn test_query_with_mode() {
+        let dict = build_dict(&[]);
+        let op = RegexOperator::Default;
+        let options = QueryOptions::default();
+
+        let tokens = query_tokens("aI", &dict, &op, &options);
+        assert_eq!(tokens, vec!["[aあアａｱ]", "[IいイＩｲ]"]);
+        let mode = |mode: SearchMode| query_with_mode("aI", &dict, &op, &options, mode);
+        assert_eq!(mode(SearchMode::Phrase), vec![query("aI".to_string(), &dict, &op)]);
+        assert_eq!(mode(SearchMode::Any), vec!["([aあアａｱ]|[IいイＩｲ])"]);
+        assert_eq!(mode(SearchMode::All), tokens);
+        assert_eq!(union_regex(&tokens[..1], &RegexOperator::Vim), "[aあアａｱ]");
+        assert_eq!(
+            union_regex(&tokens, &RegexOperator::Vim),
+            "\\%([aあアａｱ]\\|[IいイＩｲ]\\)"
+        );
+        assert!(query_with_mode(" ", &dict, &op, &options, SearchMode::Any).is_empty());
+        assert_eq!("AND".parse(), Ok(SearchMode::All));
+    }
+
+    #[test]
+    fn test_query_with_budget() {
+        let dict = build_dict(&[
+            ("か", &["蚊"]),
+            ("かい", &["貝"]),
+            ("かいがら", &["貝殻"]),
+            ("かいがらむし", &["貝殻虫"]),
+        ]);
+        let op = RegexOperator::Default;
+        let report = |options: QueryOptions| query_with_report("ka", &dict, &op, &options, SearchMode::Phrase);
+
+        let unlimited = report(QueryOptions::new());
+        assert!(!unlimited.is_truncated());
+        assert_eq!(unlimited.patterns[0], query("ka".to_string(), &dict, &op));
+
+        // 長い単語から取り除く
+        let limited = report(QueryOptions::new().full_width(false).max_candidates(Some(6)));
+        assert_eq!(limited.dropped_candidates, 2);
+        assert!(limited.patterns[0].contains('蚊') && limited.patterns[0].contains('貝'));
+        assert!(!limited.patterns[0].contains("貝殻"));
+
+        let limited = report(QueryOptions::new().max_pattern_length(Some(20)));
+        assert!(limited.is_truncated());
+        assert!(limited.patterns[0].len() <= 20);
+        let limited = report(QueryOptions::new().max_pattern_length(Some(1)));
+        assert_eq!(limited.patterns, vec!["ka"]);
+        // 0を指定しても、入力そのものは残す
+        let limited = report(QueryOptions::new().max_candidates(Some(0)));
+        assert_eq!(limited.patterns, vec!["ka"]);
+    }
+
+    #[test]
+    fn test_candidates_ranked_by_weight() {
+        let dict = dict_entries(&[("けんさ", &["検査"]), ("けんさく", &["罠索", "検索"])]);
+        let weights = HashMap::from([
+            ("検索".to_string(), 100),
+            ("検査".to_string(), 30),
+            ("罠索".to_string(), 1),
+        ]);
+        let options = BuildOptions {
+            weights: Some(weights),
+            ..BuildOptions::default()
+        };
+        let dict = CompactDictionary::new(&build_with_options(dict, &options));
+
+        let dictionary_words = |options: &QueryOptions| -> Vec<(String, u32)> {
+            query_candidates_with_options("kensa", &dict, options)
+                .into_iter()
+                .filter_map(|c| match c.source {
+                    CandidateSource::Dictionary { weight, .. } => Some((c.text, weight)),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(
+            dictionary_words(&QueryOptions::new()),
+            vec![
+                ("検索".to_string(), 100),
+                ("検査".to_string(), 30),
+                ("罠索".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            dictionary_words(&QueryOptions::new().max_dictionary_hits(Some(1))),
+            vec![("検索".to_string(), 100)]
+        );
+
+        let mut candidates = query_candidates("kensaku", &dict);
+        candidates.reverse();
+        rank_candidates(&mut candidates);
+        assert_eq!(candidates[0].source, CandidateSource::Input);
+        let position = |text: &str| candidates.iter().position(|c| c.text == text).unwrap();
+        assert!(position("検索") < position("罠索"));
+
+        // 候補を減らすときは、重みの小さい単語から取り除く
+        let options = QueryOptions::new()
+            .full_width(false)
+            .half_width_katakana(false)
+            .max_candidates(Some(4));
+        let report = query_with_report("kensaku", &dict, &RegexOperator::Default, &options, SearchMode::Phrase);
+        assert_eq!(report.dropped_candidates, 1);
+        assert!(report.patterns[0].contains("検索"));
+        assert!(!report.patterns[0].contains("罠索"));
+    }
+
+    #[test]
+    fn test_candidates_from_all_romaji_readings() {
+        let dict = build_dict(&[("てんいん", &["店員"])]);
+        let texts = |options: &QueryOptions| -> Vec<String> {
+            query_candidates_with_options("tenin", &dict, options)
+                .into_iter()
+                .map(|c| c.text)
+                .collect()
+        };
+        assert!(!texts(&QueryOptions::new()).contains(&"店員".to_string()));
+        let texts = texts(&QueryOptions::new().max_romaji_readings(8));
+        assert!(texts.contains(&"店員".to_string()));
+        assert!(texts.contains(&"てにん".to_string()));
+    }
+
+    #[test]
+    fn test_candidates_with_typos() {
+        let dict = build_dict(&[("けんさく", &["検索"]), ("けんすう", &["件数"])]);
+        let sources = |word: &str, options: &QueryOptions| -> Vec<(String, CandidateSource)> {
+            query_candidates_with_options(word, &dict, options)
+                .into_iter()
+                .filter(|c| {
+                    matches!(
+                        c.source,
+                        CandidateSource::Dictionary { .. } | CandidateSource::Fuzzy { .. }
+                    )
+                })
+                .map(|c| (c.text, c.source))
+                .collect()
+        };
+        assert_eq!(sources("kensku", &QueryOptions::new()), Vec::new());
+        assert_eq!(
+            sources("kensku", &QueryOptions::new().max_edits(1)),
+            vec![(
+                "検索".to_string(),
+                CandidateSource::Fuzzy {
+                    key: "けんさく".to_string(),
+                    distance: 1,
+                    weight: 0
+                }
+            )]
+        );
+        // 読みどおりに引ける単語は、近似検索を許しても辞書の単語のまま
+        let found = sources("kensu", &QueryOptions::new().max_edits(1));
+        assert_eq!(found[0].0, "件数");
+        assert!(matches!(found[0].1, CandidateSource::Dictionary { .. }));
+        assert!(
+            found
+                .iter()
+                .any(|(text, source)| text == "検索" && matches!(source, CandidateSource::Fuzzy { .. }))
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_candidates_stop_at_dictionary_limit() {
+        let dict = build_dict(&[
+            ("けんさく", &["検索"]),
+            ("けんそく", &["検束"]),
+            ("けんさつ", &["検札"]),
+        ]);
+        let texts = |options: &QueryOptions| -> Vec<String> {
+            query_candidates_with_options("kensku", &dict, options)
+                .into_iter()
+                .filter(|c| matches!(c.source, CandidateSource::Fuzzy { .. }))
+                .map(|c| c.text)
+                .collect()
+        };
+        let options = QueryOptions::new().max_edits(2);
+        assert_eq!(texts(&options).last().map(String::as_str), Some("検札"));
+        // 編集距離1の「けんさく」と「けんそく」のどちらかで上限に達し、距離2の読みは使わない
+        let limited = texts(&options.max_dictionary_hits(Some(1)));
+        assert_eq!(limited.len(), 1);
+        assert_ne!(limited[0], "検札");
     }
 }
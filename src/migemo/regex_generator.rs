@@ -13,6 +13,22 @@ pub enum RegexOperator {
     Emacs,
     VimNonNewline,
     EmacsNonNewline,
+    /// `regex`クレート (ripgrepの既定のエンジン) で必ずコンパイルできる正規表現。文字の間の改行にもマッチする。
+    Rust,
+    /// `regex`クレート (ripgrepの既定のエンジン) で必ずコンパイルできる正規表現
+    RustNonNewline,
+    /// PCRE (`rg -P`、`grep -P`、PHPなど)。文字の間の改行にもマッチする。
+    Pcre,
+    PcreNonNewline,
+    /// ECMAScriptの`u`フラグ付きの正規表現。文字の間の改行にもマッチする。
+    EcmaScript,
+    EcmaScriptNonNewline,
+    /// .NETの正規表現。文字の間の改行にもマッチする。
+    DotNet,
+    DotNetNonNewline,
+    /// POSIXの拡張正規表現 (`grep -E`、`awk`など)。文字の間の空白や改行にもマッチする。
+    PosixEre,
+    PosixEreNonNewline,
     User {
         or: String,
         begin_group: String,
@@ -20,9 +36,92 @@ pub enum RegexOperator {
         begin_class: String,
         end_class: String,
         newline: String,
+        /// バックスラッシュでエスケープする文字 (ASCIIのみ)
+        escape: String,
+        /// 文字クラスの中でバックスラッシュでエスケープする文字 (ASCIIのみ)。
+        /// 空の場合は、POSIXのブラケット表現と同じく、文字の並べ方で特別な意味を避ける。
+        class_escape: String,
     },
 }
 
+impl RegexOperator {
+    /// 名前から正規表現の方言を選ぶ。`newline`が`true`なら、文字の間の改行にもマッチする方を選ぶ。
+    ///
+    /// `default`、`vim`、`emacs`、`rust` (`ripgrep`)、`pcre`、`ecmascript` (`js`)、`dotnet`、`ere`を受け付ける。
+    pub fn from_dialect(name: &str, newline: bool) -> Option<RegexOperator> {
+        let operator = match (name.to_ascii_lowercase().as_str(), newline) {
+            ("default", _) => RegexOperator::Default,
+            ("vim", true) => RegexOperator::Vim,
+            ("vim", false) => RegexOperator::VimNonNewline,
+            ("emacs", true) => RegexOperator::Emacs,
+            ("emacs", false) => RegexOperator::EmacsNonNewline,
+            ("rust" | "ripgrep" | "rg", true) => RegexOperator::Rust,
+            ("rust" | "ripgrep" | "rg", false) => RegexOperator::RustNonNewline,
+            ("pcre", true) => RegexOperator::Pcre,
+            ("pcre", false) => RegexOperator::PcreNonNewline,
+            ("ecmascript" | "javascript" | "js", true) => RegexOperator::EcmaScript,
+            ("ecmascript" | "javascript" | "js", false) => RegexOperator::EcmaScriptNonNewline,
+            ("dotnet" | ".net", true) => RegexOperator::DotNet,
+            ("dotnet" | ".net", false) => RegexOperator::DotNetNonNewline,
+            ("ere" | "posix-ere", true) => RegexOperator::PosixEre,
+            ("ere" | "posix-ere", false) => RegexOperator::PosixEreNonNewline,
+            _ => return None,
+        };
+        Some(operator)
+    }
+}
+
+/// エスケープする文字の集合を、ASCIIの範囲のビットマップにする
+pub const fn escape_bitmap(chars: &str) -> [u64; 2] {
+    let mut bitmap = [0u64; 2];
+    let bytes = chars.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i] as u64;
+        if c < 128 {
+            bitmap[(c / 64) as usize] |= 1 << (c % 64);
+        }
+        i += 1;
+    }
+    bitmap
+}
+
+/// 多くの正規表現エンジンで特別な意味を持つ文字
+pub const DEFAULT_ESCAPE_BITMAP: [u64; 2] = escape_bitmap(r"()[]{}?*+|^$.\");
+
+/// `regex`クレートで特別な意味を持つ文字。文字クラスの中の集合演算 (`--`、`&&`、`~~`) と、
+/// 冗長モードのコメント (`#`) に使われる文字も含める。
+pub const RUST_ESCAPE_BITMAP: [u64; 2] = escape_bitmap(r"()[]{}?*+|^$.\-&~#");
+
+/// Vimのmagicモードで特別な意味を持つ文字。`+`や`(`などは、エスケープするとかえって特別な意味になる。
+/// 検索コマンドの区切りになる`/`も含める。
+pub const VIM_ESCAPE_BITMAP: [u64; 2] = escape_bitmap(r"\.*[~^$/");
+
+/// Vimの文字クラスの中でエスケープする文字。これ以外の文字の前のバックスラッシュは、文字そのものになる。
+pub const VIM_CLASS_ESCAPE_BITMAP: [u64; 2] = escape_bitmap(r"\]^-");
+
+/// Emacsで特別な意味を持つ文字。`(`、`|`、`{`などは、エスケープするとかえって特別な意味になる。
+pub const EMACS_ESCAPE_BITMAP: [u64; 2] = escape_bitmap(r"\.*+?[^$");
+
+/// PCREと.NETで特別な意味を持つ文字。英数字以外はエスケープしても常に文字そのものになる。
+pub const PCRE_ESCAPE_BITMAP: [u64; 2] = escape_bitmap(r"()[]{}?*+|^$.\-#");
+
+/// ECMAScriptの`u`フラグ付きの正規表現で、エスケープできる文字。
+/// `u`フラグでは、これ以外の文字をエスケープすると構文エラーになる。
+pub const ECMASCRIPT_ESCAPE_BITMAP: [u64; 2] = escape_bitmap(r"()[]{}?*+|^$.\/");
+
+/// ECMAScriptの`u`フラグ付きの正規表現で、文字クラスの中でエスケープする文字
+pub const ECMASCRIPT_CLASS_ESCAPE_BITMAP: [u64; 2] = escape_bitmap(r"()[]{}?*+|^$.\/-");
+
+/// POSIXの拡張正規表現で特別な意味を持つ文字。`]`と`}`は単独では文字そのものになる。
+pub const POSIX_ERE_ESCAPE_BITMAP: [u64; 2] = escape_bitmap(r"()[{?*+|^$.\");
+
+/// 非捕獲グループを使える正規表現で、文字の間に入れる改行のパターン
+pub(crate) const NEWLINE: &str = r"(?:\r?\n)?";
+
+/// 非捕獲グループを使える正規表現で、文字の間に入れる空白のパターン
+const WHITESPACE: &str = r"\s*";
+
 #[derive(Debug)]
 pub struct RegexOperatorDetail {
     pub or: String,
@@ -31,9 +130,68 @@ pub struct RegexOperatorDetail {
     pub begin_class: String,
     pub end_class: String,
     pub newline: String,
+    /// 改行だけでなく、任意の空白にマッチするパターン。`QueryOptions::whitespace`で`newline`の代わりに使う。
+    pub whitespace: String,
+    /// バックスラッシュでエスケープするASCII文字のビットマップ
+    pub escape_bitmap: [u64; 2],
+    /// 文字クラスの中でバックスラッシュでエスケープするASCII文字のビットマップ。
+    /// 空の場合は、POSIXのブラケット表現と同じく、文字の並べ方で特別な意味を避ける。
+    pub class_escape_bitmap: [u64; 2],
+}
+
+fn bitmap_contains(bitmap: &[u64; 2], c: char) -> bool {
+    if c < '\u{80}' {
+        (bitmap[(c as usize) / 64] >> ((c as u64) % 64)) & 1 == 1
+    } else {
+        false
+    }
 }
 
 impl RegexOperatorDetail {
+    /// 文字をエスケープする必要があるか
+    pub fn needs_escape(&self, c: char) -> bool {
+        bitmap_contains(&self.escape_bitmap, c)
+    }
+
+    /// 文字クラスの中で、文字をエスケープする必要があるか
+    pub fn needs_class_escape(&self, c: char) -> bool {
+        bitmap_contains(&self.class_escape_bitmap, c)
+    }
+
+    /// 文字クラスの外に、1文字を必要に応じてエスケープして書き出す
+    pub fn push_literal(&self, c: char, buf: &mut String) {
+        if self.needs_escape(c) {
+            buf.push('\\');
+        }
+        buf.push(c);
+    }
+
+    /// 文字の集合を、文字クラスとして書き出す
+    pub fn push_class(&self, chars: &[char], buf: &mut String) {
+        buf.push_str(&self.begin_class);
+        if self.class_escape_bitmap == [0, 0] {
+            // `]`は先頭、`-`は末尾に置き、`^`は先頭に置かない。`[`の直後に`.:=`が続くと
+            // 照合要素などの始まりになるため、`[`は`-`の直前か末尾に置く。
+            let mut ordered: Vec<char> = chars.iter().copied().filter(|c| *c == ']').collect();
+            ordered.extend(chars.iter().copied().filter(|c| !"]^[-".contains(*c)));
+            for special in ['^', '[', '-'] {
+                ordered.extend(chars.iter().copied().filter(|c| *c == special));
+            }
+            if ordered.len() > 1 && ordered[0] == '^' {
+                ordered.swap(0, 1);
+            }
+            buf.extend(ordered);
+        } else {
+            for &c in chars {
+                if self.needs_class_escape(c) {
+                    buf.push('\\');
+                }
+                buf.push(c);
+            }
+        }
+        buf.push_str(&self.end_class);
+    }
+
     pub fn get_regex_operator_detail(rxop: &RegexOperator) -> RegexOperatorDetail {
         return match rxop {
             RegexOperator::Default => RegexOperatorDetail {
@@ -43,6 +201,9 @@ impl RegexOperatorDetail {
                 begin_class: "[".to_string(),
                 end_class: "]".to_string(),
                 newline: String::new(),
+                whitespace: String::new(),
+                escape_bitmap: DEFAULT_ESCAPE_BITMAP,
+                class_escape_bitmap: DEFAULT_ESCAPE_BITMAP,
             },
             RegexOperator::Vim => RegexOperatorDetail {
                 or: "\\|".to_string(),
@@ -51,6 +212,9 @@ impl RegexOperatorDetail {
                 begin_class: "[".to_string(),
                 end_class: "]".to_string(),
                 newline: "\\_s*".to_string(),
+                whitespace: "\\_s*".to_string(),
+                escape_bitmap: VIM_ESCAPE_BITMAP,
+                class_escape_bitmap: VIM_CLASS_ESCAPE_BITMAP,
             },
             RegexOperator::VimNonNewline => RegexOperatorDetail {
                 or: "\\|".to_string(),
@@ -59,6 +223,9 @@ impl RegexOperatorDetail {
                 begin_class: "[".to_string(),
                 end_class: "]".to_string(),
                 newline: String::new(),
+                whitespace: "\\_s*".to_string(),
+                escape_bitmap: VIM_ESCAPE_BITMAP,
+                class_escape_bitmap: VIM_CLASS_ESCAPE_BITMAP,
             },
             RegexOperator::Emacs => RegexOperatorDetail {
                 or: "\\|".to_string(),
@@ -67,6 +234,119 @@ impl RegexOperatorDetail {
                 begin_class: "[".to_string(),
                 end_class: "]".to_string(),
                 newline: "\\_s-*".to_string(),
+                whitespace: "\\_s-*".to_string(),
+                escape_bitmap: EMACS_ESCAPE_BITMAP,
+                class_escape_bitmap: [0, 0],
+            },
+            RegexOperator::Rust => RegexOperatorDetail {
+                or: "|".to_string(),
+                begin_group: "(?:".to_string(),
+                end_group: ")".to_string(),
+                begin_class: "[".to_string(),
+                end_class: "]".to_string(),
+                newline: NEWLINE.to_string(),
+                whitespace: WHITESPACE.to_string(),
+                escape_bitmap: RUST_ESCAPE_BITMAP,
+                class_escape_bitmap: RUST_ESCAPE_BITMAP,
+            },
+            RegexOperator::RustNonNewline => RegexOperatorDetail {
+                or: "|".to_string(),
+                begin_group: "(?:".to_string(),
+                end_group: ")".to_string(),
+                begin_class: "[".to_string(),
+                end_class: "]".to_string(),
+                newline: String::new(),
+                whitespace: WHITESPACE.to_string(),
+                escape_bitmap: RUST_ESCAPE_BITMAP,
+                class_escape_bitmap: RUST_ESCAPE_BITMAP,
+            },
+            RegexOperator::Pcre => RegexOperatorDetail {
+                or: "|".to_string(),
+                begin_group: "(?:".to_string(),
+                end_group: ")".to_string(),
+                begin_class: "[".to_string(),
+                end_class: "]".to_string(),
+                newline: NEWLINE.to_string(),
+                whitespace: WHITESPACE.to_string(),
+                escape_bitmap: PCRE_ESCAPE_BITMAP,
+                class_escape_bitmap: PCRE_ESCAPE_BITMAP,
+            },
+            RegexOperator::PcreNonNewline => RegexOperatorDetail {
+                or: "|".to_string(),
+                begin_group: "(?:".to_string(),
+                end_group: ")".to_string(),
+                begin_class: "[".to_string(),
+                end_class: "]".to_string(),
+                newline: String::new(),
+                whitespace: WHITESPACE.to_string(),
+                escape_bitmap: PCRE_ESCAPE_BITMAP,
+                class_escape_bitmap: PCRE_ESCAPE_BITMAP,
+            },
+            RegexOperator::EcmaScript => RegexOperatorDetail {
+                or: "|".to_string(),
+                begin_group: "(?:".to_string(),
+                end_group: ")".to_string(),
+                begin_class: "[".to_string(),
+                end_class: "]".to_string(),
+                newline: NEWLINE.to_string(),
+                whitespace: WHITESPACE.to_string(),
+                escape_bitmap: ECMASCRIPT_ESCAPE_BITMAP,
+                class_escape_bitmap: ECMASCRIPT_CLASS_ESCAPE_BITMAP,
+            },
+            RegexOperator::EcmaScriptNonNewline => RegexOperatorDetail {
+                or: "|".to_string(),
+                begin_group: "(?:".to_string(),
+                end_group: ")".to_string(),
+                begin_class: "[".to_string(),
+                end_class: "]".to_string(),
+                newline: String::new(),
+                whitespace: WHITESPACE.to_string(),
+                escape_bitmap: ECMASCRIPT_ESCAPE_BITMAP,
+                class_escape_bitmap: ECMASCRIPT_CLASS_ESCAPE_BITMAP,
+            },
+            RegexOperator::DotNet => RegexOperatorDetail {
+                or: "|".to_string(),
+                begin_group: "(?:".to_string(),
+                end_group: ")".to_string(),
+                begin_class: "[".to_string(),
+                end_class: "]".to_string(),
+                newline: NEWLINE.to_string(),
+                whitespace: WHITESPACE.to_string(),
+                escape_bitmap: PCRE_ESCAPE_BITMAP,
+                class_escape_bitmap: PCRE_ESCAPE_BITMAP,
+            },
+            RegexOperator::DotNetNonNewline => RegexOperatorDetail {
+                or: "|".to_string(),
+                begin_group: "(?:".to_string(),
+                end_group: ")".to_string(),
+                begin_class: "[".to_string(),
+                end_class: "]".to_string(),
+                newline: String::new(),
+                whitespace: WHITESPACE.to_string(),
+                escape_bitmap: PCRE_ESCAPE_BITMAP,
+                class_escape_bitmap: PCRE_ESCAPE_BITMAP,
+            },
+            RegexOperator::PosixEre => RegexOperatorDetail {
+                or: "|".to_string(),
+                begin_group: "(".to_string(),
+                end_group: ")".to_string(),
+                begin_class: "[".to_string(),
+                end_class: "]".to_string(),
+                newline: "[[:space:]]*".to_string(),
+                whitespace: "[[:space:]]*".to_string(),
+                escape_bitmap: POSIX_ERE_ESCAPE_BITMAP,
+                class_escape_bitmap: [0, 0],
+            },
+            RegexOperator::PosixEreNonNewline => RegexOperatorDetail {
+                or: "|".to_string(),
+                begin_group: "(".to_string(),
+                end_group: ")".to_string(),
+                begin_class: "[".to_string(),
+                end_class: "]".to_string(),
+                newline: String::new(),
+                whitespace: "[[:space:]]*".to_string(),
+                escape_bitmap: POSIX_ERE_ESCAPE_BITMAP,
+                class_escape_bitmap: [0, 0],
             },
             RegexOperator::EmacsNonNewline => RegexOperatorDetail {
                 or: "\\|".to_string(),
@@ -75,6 +355,9 @@ impl RegexOperatorDetail {
                 begin_class: "[".to_string(),
                 end_class: "]".to_string(),
                 newline: String::new(),
+                whitespace: "\\_s-*".to_string(),
+                escape_bitmap: EMACS_ESCAPE_BITMAP,
+                class_escape_bitmap: [0, 0],
             },
             RegexOperator::User {
                 or,
@@ -83,6 +366,8 @@ impl RegexOperatorDetail {
                 begin_class,
                 end_class,
                 newline,
+                escape,
+                class_escape,
             } => RegexOperatorDetail {
                 or: or.clone(),
                 begin_group: begin_group.clone(),
@@ -90,6 +375,9 @@ impl RegexOperatorDetail {
                 begin_class: begin_class.clone(),
                 end_class: end_class.clone(),
                 newline: newline.clone(),
+                whitespace: newline.clone(),
+                escape_bitmap: escape_bitmap(escape),
+                class_escape_bitmap: escape_bitmap(class_escape),
             },
         };
     }
@@ -98,7 +386,12 @@ impl RegexOperatorDetail {
 /// 正規表現ジェネレータの共通トレイト
 pub trait RegexGeneratorTrait {
     fn add(&mut self, word: &[char]);
-    fn generate(&self, operator: &RegexOperator) -> String;
+
+    fn generate_with_detail(&self, operator: &RegexOperatorDetail) -> String;
+
+    fn generate(&self, operator: &RegexOperator) -> String {
+        self.generate_with_detail(&RegexOperatorDetail::get_regex_operator_detail(operator))
+    }
 }
 
 #[derive(Debug)]
@@ -139,7 +432,7 @@ impl RegexGenerator {
             return Some(Box::new(RegexNode {
                 code: code,
                 child: RegexGenerator::_add(None, word, offset + 1),
-                next: ::std::mem::replace(&mut node.unwrap().next, None),
+                next: node,
             }));
         } else {
             fn find_le_node(node: &mut Box<RegexNode>, code: char) -> &mut Box<RegexNode> {
@@ -197,8 +490,6 @@ impl RegexGenerator {
     }
 
     fn generate_stub(&self, node: &Option<Box<RegexNode>>, operator: &RegexOperatorDetail, buf: &mut String) {
-        let mut escape_characters: Vec<char> = "\\.[]{}()*+-?^$|".chars().collect();
-        escape_characters.sort();
         let mut brother = 1;
         let mut haschild = 0;
         let mut tmp = node;
@@ -218,24 +509,19 @@ impl RegexGenerator {
             buf.push_str(&operator.begin_group);
         }
         if nochild > 0 {
-            if nochild > 1 {
-                buf.push_str(&operator.begin_class);
-            }
+            let mut chars: Vec<char> = Vec::new();
             let mut tmp = node;
             while tmp.is_some() {
                 let tmp_unwrap = tmp.as_ref().unwrap();
-                if tmp_unwrap.child.is_some() {
-                    tmp = &tmp_unwrap.next;
-                    continue;
-                }
-                if escape_characters.binary_search(&tmp_unwrap.code).is_ok() {
-                    buf.push('\\');
+                if tmp_unwrap.child.is_none() {
+                    chars.push(tmp_unwrap.code);
                 }
-                buf.push(::std::char::from_u32(tmp_unwrap.code as u32).unwrap());
                 tmp = &tmp_unwrap.next;
             }
             if nochild > 1 {
-                buf.push_str(&operator.end_class);
+                operator.push_class(&chars, buf);
+            } else {
+                operator.push_literal(chars[0], buf);
             }
         }
 
@@ -248,10 +534,7 @@ impl RegexGenerator {
                 tmp = &tmp.as_ref().unwrap().next;
             }
             loop {
-                if escape_characters.binary_search(&tmp.as_ref().unwrap().code).is_ok() {
-                    buf.push('\\');
-                }
-                buf.push(tmp.as_ref().unwrap().code);
+                operator.push_literal(tmp.as_ref().unwrap().code, buf);
                 if operator.newline.len() > 0 {
                     buf.push_str(&operator.newline);
                 }
@@ -282,12 +565,11 @@ impl RegexGeneratorTrait for RegexGenerator {
         self.root = RegexGenerator::_add(::std::mem::replace(&mut self.root, None), word, 0);
     }
 
-    fn generate(&self, operator: &RegexOperator) -> String {
+    fn generate_with_detail(&self, operator: &RegexOperatorDetail) -> String {
         return match &self.root {
             Some(_) => {
                 let mut string: String = String::new();
-                let operator_detail = RegexOperatorDetail::get_regex_operator_detail(operator);
-                self.generate_stub(&self.root, &operator_detail, &mut string);
+                self.generate_stub(&self.root, operator, &mut string);
                 string
             }
             None => "".to_string(),
@@ -363,4 +645,111 @@ mod tests {
         let expected = "[𠮟𠮷]";
         assert_eq!(actual, expected);
     }
+
+    fn generate_ternary(words: &[&str], rxop: &RegexOperator) -> String {
+        let mut rxgen = crate::migemo::ternary_regex_generator::TernaryRegexGenerator::new();
+        for word in words {
+            let word: Vec<char> = word.chars().collect();
+            rxgen.add(&word);
+        }
+        rxgen.generate(rxop)
+    }
+
+    #[test]
+    fn dialects() {
+        let words = ["a+b", "a+c", "a-x", "a/y", "a#z"];
+        let pcre = generate_ternary(&words, &RegexOperator::PcreNonNewline);
+        assert_eq!(pcre, "a(?:\\#z|\\+[bc]|\\-x|/y)");
+        // `u`フラグでは、文字クラスの外の`\-`や`\#`は構文エラーになる
+        let ecmascript = generate_ternary(&words, &RegexOperator::EcmaScriptNonNewline);
+        assert_eq!(ecmascript, "a(?:#z|\\+[bc]|-x|\\/y)");
+        let ere = generate_ternary(&words, &RegexOperator::PosixEreNonNewline);
+        assert_eq!(ere, "a(#z|\\+[bc]|-x|/y)");
+        let pcre = generate_ternary(&["ab", "ac"], &RegexOperator::Pcre);
+        assert_eq!(pcre, "a(?:\\r?\\n)?[bc]");
+    }
+
+    #[test]
+    fn class_escapes() {
+        let words = ["+", "-", "a", "]", "^"];
+        assert_eq!(
+            generate_ternary(&words, &RegexOperator::PcreNonNewline),
+            "[\\+\\-\\]\\^a]"
+        );
+        assert_eq!(
+            generate_ternary(&words, &RegexOperator::EcmaScriptNonNewline),
+            "[\\+\\-\\]\\^a]"
+        );
+        // ブラケット表現ではエスケープできないため、並べ方で特別な意味を避ける
+        assert_eq!(generate_ternary(&words, &RegexOperator::PosixEreNonNewline), "[]+a^-]");
+        assert_eq!(
+            generate_ternary(&["^", "-"], &RegexOperator::PosixEreNonNewline),
+            "[-^]"
+        );
+        assert_eq!(
+            generate_ternary(&["[", "^", "."], &RegexOperator::PosixEreNonNewline),
+            "[.^[]"
+        );
+        assert_eq!(
+            generate_ternary(&["[", "^"], &RegexOperator::PosixEreNonNewline),
+            "[[^]"
+        );
+    }
+
+    #[test]
+    fn vim_and_emacs_escapes() {
+        // Vimのmagicモードでは`+`や`(`は文字そのもの、`~`や`/`はエスケープが必要
+        let words = ["a+b", "a(c", "a.d", "a~e", "a/f"];
+        assert_eq!(
+            generate_ternary(&words, &RegexOperator::VimNonNewline),
+            "a\\%((c\\|+b\\|\\.d\\|\\/f\\|\\~e\\)"
+        );
+        // Emacsでは`(`や`|`は文字そのもの、`+`や`?`はエスケープが必要
+        let words = ["a+b", "a(c", "a.d", "a|e", "a?f"];
+        assert_eq!(
+            generate_ternary(&words, &RegexOperator::EmacsNonNewline),
+            "a\\((c\\|\\+b\\|\\.d\\|\\?f\\||e\\)"
+        );
+        assert_eq!(
+            generate_ternary(&["]", "^", "-", "a"], &RegexOperator::VimNonNewline),
+            "[\\-\\]\\^a]"
+        );
+        assert_eq!(
+            generate_ternary(&["]", "^", "-", "a"], &RegexOperator::EmacsNonNewline),
+            "[]a^-]"
+        );
+    }
+
+    #[test]
+    fn user_escapes() {
+        let rxop = RegexOperator::User {
+            or: "|".to_string(),
+            begin_group: "(".to_string(),
+            end_group: ")".to_string(),
+            begin_class: "[".to_string(),
+            end_class: "]".to_string(),
+            newline: String::new(),
+            escape: "@".to_string(),
+            class_escape: "!".to_string(),
+        };
+        assert_eq!(generate_ternary(&["a!", "a."], &rxop), "a[\\!.]");
+        assert_eq!(generate_ternary(&["@.", "@x"], &rxop), "\\@[.x]");
+    }
+
+    #[test]
+    fn from_dialect() {
+        assert!(matches!(
+            RegexOperator::from_dialect("PCRE", true),
+            Some(RegexOperator::Pcre)
+        ));
+        assert!(matches!(
+            RegexOperator::from_dialect("rg", false),
+            Some(RegexOperator::RustNonNewline)
+        ));
+        assert!(matches!(
+            RegexOperator::from_dialect("default", true),
+            Some(RegexOperator::Default)
+        ));
+        assert!(RegexOperator::from_dialect("sed", true).is_none());
+    }
 }
@@ -1,7 +1,10 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::OnceLock;
 
 use crate::migemo::bit_list::BitList;
 use crate::migemo::bit_vector::BitVector;
+use crate::migemo::character_converter::kata2hira;
 use crate::migemo::louds_trie::LoudsTrie;
 
 #[derive(Clone, Default)]
@@ -15,8 +18,155 @@ pub struct RomajiProcessor {
     key_terminals: BitVector,
     value_trie: LoudsTrie,
     node_mappings: Vec<Option<MappingEntry>>,
+    // 仮名からローマ字を引く索引。仮名からローマ字に変換するときに作る
+    reverse_index: OnceLock<ReverseIndex>,
 }
 
+// 仮名から、その仮名になるローマ字と次の入力に残す文字数を引く
+struct ReverseIndex {
+    spellings: HashMap<Vec<u16>, Vec<(Vec<u16>, usize)>>,
+    // 最も長い仮名のUTF-16での長さ
+    max_kana_len: usize,
+}
+
+// IMEが互換のために受け付けるつづりで使う文字。同じ長さのつづりがあれば、これを含まないものを選ぶ。
+const ALIAS_ROMAJI_CHARS: [u16; 4] = [b'c' as u16, b'l' as u16, b'q' as u16, b'\'' as u16];
+
+impl ReverseIndex {
+    fn new(processor: &RomajiProcessor) -> ReverseIndex {
+        let mut spellings: HashMap<Vec<u16>, Vec<(Vec<u16>, usize)>> = HashMap::new();
+        for (node_index, entry) in processor.node_mappings.iter().enumerate() {
+            let entry = match entry {
+                Some(entry) if processor.key_terminals.get(node_index) => entry,
+                _ => continue,
+            };
+            let kana = processor.value_trie.get_key(entry.value_index as usize);
+            // `www`→`w`のように、ローマ字を仮名にしない項目は使わない
+            if kana.iter().any(|&c| c < 0x80) {
+                continue;
+            }
+            let romaji = processor.key_trie.get_key(node_index);
+            spellings.entry(kana).or_default().push((romaji, entry.remain as usize));
+        }
+        // 打つ文字数が少なく、互換のためのつづりでないものを先に試す
+        for candidates in spellings.values_mut() {
+            candidates.sort_by_key(|(romaji, remain)| {
+                let is_alias = romaji.iter().any(|c| ALIAS_ROMAJI_CHARS.contains(c));
+                (romaji.len() - remain, is_alias, romaji.clone())
+            });
+        }
+        let max_kana_len = spellings.keys().map(|kana| kana.len()).max().unwrap_or(0);
+        ReverseIndex {
+            spellings,
+            max_kana_len,
+        }
+    }
+
+    // `kana`の先頭に一致する仮名を、長い順に返す
+    fn prefixes<'a>(&'a self, kana: &'a [u16]) -> impl Iterator<Item = (usize, &'a [(Vec<u16>, usize)])> + 'a {
+        (1..=self.max_kana_len.min(kana.len())).rev().filter_map(|len| {
+            self.spellings
+                .get(&kana[..len])
+                .map(|spellings| (len, spellings.as_slice()))
+        })
+    }
+}
+
+// 仮名をローマ字にするときの途中の状態
+struct Speller<'a> {
+    processor: &'a RomajiProcessor,
+    index: &'a ReverseIndex,
+    kana: &'a [u16],
+    romaji: Vec<u16>,
+    // 仮名が続いている範囲の先頭の、仮名とローマ字での位置
+    run_start: (usize, usize),
+    results: Vec<String>,
+    limit: usize,
+}
+
+impl Speller<'_> {
+    // `pos`以降の仮名をローマ字にする。`retained`は、直前の項目が次の入力に残した文字。
+    fn spell(&mut self, pos: usize, retained: &[u16]) {
+        if self.results.len() >= self.limit {
+            return;
+        }
+        if pos == self.kana.len() {
+            if retained.is_empty() {
+                self.results.push(String::from_utf16_lossy(&self.romaji));
+            }
+            return;
+        }
+        let (index, kana) = (self.index, self.kana);
+        let mut prefixes = index.prefixes(&kana[pos..]).peekable();
+        if prefixes.peek().is_none() {
+            // 仮名でない文字はそのまま使い、その後ろから新しく仮名の範囲を始める
+            if !retained.is_empty() {
+                return;
+            }
+            let run_start = self.run_start;
+            self.romaji.push(self.kana[pos]);
+            self.run_start = (pos + 1, self.romaji.len());
+            self.spell(pos + 1, &[]);
+            self.romaji.pop();
+            self.run_start = run_start;
+            return;
+        }
+        for (len, spellings) in prefixes {
+            for (romaji, remain) in spellings {
+                if !romaji.starts_with(retained) {
+                    continue;
+                }
+                let end = self.romaji.len();
+                self.romaji.extend_from_slice(&romaji[..romaji.len() - remain]);
+                // 次の入力に文字を残す項目は、次の項目を加えてから確かめる
+                if *remain > 0 || self.reproduces(pos + len) {
+                    self.spell(pos + len, &romaji[romaji.len() - remain..]);
+                }
+                self.romaji.truncate(end);
+                if self.results.len() >= self.limit {
+                    return;
+                }
+            }
+        }
+    }
+
+    // 仮名が続いている範囲のローマ字が、`end`までの仮名に戻るかどうか
+    fn reproduces(&self, end: usize) -> bool {
+        let (kana_start, romaji_start) = self.run_start;
+        let romaji = String::from_utf16_lossy(&self.romaji[romaji_start..]);
+        let hiragana: Vec<u16> = self.processor.romaji_to_hiragana(&romaji).encode_utf16().collect();
+        hiragana == self.kana[kana_start..end]
+    }
+}
+
+/// ローマ字の変換表を読み込めなかった理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomajiTableError {
+    /// 列が足りないか、ローマ字または仮名が空になっている行。行番号は1から数える。
+    MalformedLine { line: usize },
+    /// 次の入力が、ローマ字の末尾と一致しない行
+    UnsupportedNextInput { line: usize },
+    /// 残す文字数がローマ字の長さ以上になっているか、ローマ字か仮名が空になっている
+    InvalidEntry { romaji: String },
+    /// 仮名の種類が多すぎて、変換表に格納できない
+    TooManyEntries,
+}
+
+impl fmt::Display for RomajiTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomajiTableError::MalformedLine { line } => write!(f, "malformed line {}", line),
+            RomajiTableError::UnsupportedNextInput { line } => {
+                write!(f, "next input is not a suffix of the input at line {}", line)
+            }
+            RomajiTableError::InvalidEntry { romaji } => write!(f, "invalid entry for {:?}", romaji),
+            RomajiTableError::TooManyEntries => write!(f, "too many entries"),
+        }
+    }
+}
+
+impl std::error::Error for RomajiTableError {}
+
 pub struct RomajiPredictiveResult {
     pub prefix: Vec<u16>,
     pub suffixes: Vec<Vec<u16>>,
@@ -56,7 +206,63 @@ impl RomajiProcessor {
             key_terminals,
             value_trie,
             node_mappings,
+            reverse_index: OnceLock::new(),
+        }
+    }
+
+    /// `(ローマ字, 仮名, 次の入力に残す文字数)`の一覧から変換表を作る
+    ///
+    /// 例えば`("kk", "っ", 1)`は「っ」を出力し、末尾の`k`を次の入力に使う。
+    /// 同じローマ字が複数あれば、後のものを使う。
+    pub fn from_entries<R: AsRef<str>, K: AsRef<str>>(
+        entries: &[(R, K, usize)],
+    ) -> Result<RomajiProcessor, RomajiTableError> {
+        let mut table: BTreeMap<Vec<u16>, (Vec<u16>, usize)> = BTreeMap::new();
+        for (romaji, kana, remain) in entries {
+            let (romaji, kana) = (romaji.as_ref(), kana.as_ref());
+            let key: Vec<u16> = romaji.encode_utf16().collect();
+            if key.is_empty() || kana.is_empty() || *remain >= key.len() {
+                return Err(RomajiTableError::InvalidEntry {
+                    romaji: romaji.to_string(),
+                });
+            }
+            table.insert(key, (kana.encode_utf16().collect(), *remain));
+        }
+
+        let keys: Vec<Vec<u16>> = table.keys().cloned().collect();
+        let (key_trie, _) = LoudsTrie::build(&keys);
+        let mut values: Vec<Vec<u16>> = table.values().map(|(kana, _)| kana.clone()).collect();
+        values.sort();
+        values.dedup();
+        let (value_trie, _) = LoudsTrie::build(&values);
+
+        let mut terminal_flags = BitList::new_with_size(key_trie.edges.len());
+        let mut node_mappings = vec![None; key_trie.edges.len()];
+        for (key, (kana, remain)) in &table {
+            let node_index = key_trie.get(key).unwrap();
+            let value_index = value_trie.get(kana).unwrap();
+            terminal_flags.set(node_index, true);
+            node_mappings[node_index] = Some(MappingEntry {
+                value_index: u16::try_from(value_index).map_err(|_| RomajiTableError::TooManyEntries)?,
+                remain: u8::try_from(*remain).map_err(|_| RomajiTableError::TooManyEntries)?,
+            });
         }
+        let key_terminals = BitVector::new(terminal_flags.words().to_vec(), terminal_flags.len());
+        Ok(RomajiProcessor {
+            key_trie,
+            key_terminals,
+            value_trie,
+            node_mappings,
+            reverse_index: OnceLock::new(),
+        })
+    }
+
+    /// Google日本語入力のローマ字テーブルの形式で書かれた変換表を読み込む
+    ///
+    /// 各行は`ローマ字<TAB>仮名[<TAB>次の入力]`の形式で、AZIKなどの配布されている表もこの形式で読める。
+    /// 次の入力はローマ字の末尾と一致していなければならない。空行と`#`で始まる行は読み飛ばす。
+    pub fn from_table(text: &str) -> Result<RomajiProcessor, RomajiTableError> {
+        RomajiProcessor::from_entries(&parse_romaji_table(text)?)
     }
 
     pub fn romaji_to_hiragana(&self, romaji: &str) -> String {
@@ -96,6 +302,31 @@ impl RomajiProcessor {
         String::from_utf16(&hiragana).unwrap_or_default()
     }
 
+    /// 仮名をローマ字にする
+    ///
+    /// この変換表で打つとその仮名になるつづりのうち、打つ文字数が少ないものを返す。
+    /// カタカナはひらがなとして扱い、仮名でない文字はそのまま残す。
+    /// ヘボン式や訓令式のつづりは、`RomajiScheme`の変換表で変換すると得られる。
+    pub fn kana_to_romaji(&self, kana: &str) -> Option<String> {
+        self.kana_to_romaji_all(kana, 1).pop()
+    }
+
+    /// 仮名をローマ字にするつづりを、`kana_to_romaji`で選ぶ順に最大`limit`個返す
+    pub fn kana_to_romaji_all(&self, kana: &str, limit: usize) -> Vec<String> {
+        let kana: Vec<u16> = kata2hira(kana).encode_utf16().collect();
+        let mut speller = Speller {
+            processor: self,
+            index: self.reverse_index.get_or_init(|| ReverseIndex::new(self)),
+            kana: &kana,
+            romaji: Vec::new(),
+            run_start: (0, 0),
+            results: Vec::new(),
+            limit,
+        };
+        speller.spell(0, &[]);
+        speller.results
+    }
+
     fn mapping_entry(&self, node_index: usize) -> Option<&MappingEntry> {
         self.node_mappings.get(node_index).and_then(|entry| entry.as_ref())
     }
@@ -123,6 +354,13 @@ impl RomajiProcessor {
         let mut start = 0;
 
         while start < romaji.len() {
+            if let Some(suffixes) = self.predictive_suffixes(romaji, start) {
+                return RomajiPredictiveResult {
+                    prefix: hiragana,
+                    suffixes,
+                };
+            }
+
             let query = &romaji[start..];
             let mut last_found: Option<(usize, usize)> = None;
             for (len, node_index) in self.key_trie.common_prefix_search(query).enumerate() {
@@ -132,47 +370,6 @@ impl RomajiProcessor {
                 }
             }
 
-            let terminal_nodes = self.terminal_nodes_for_prefix(query);
-            if terminal_nodes.len() > 1 {
-                let mut set: HashSet<Vec<u16>> = HashSet::new();
-                for node_index in terminal_nodes {
-                    let entry = match self.mapping_entry(node_index) {
-                        Some(entry) => entry,
-                        None => continue,
-                    };
-                    if entry.remain > 0 {
-                        let remain = entry.remain as usize;
-                        if romaji.len() >= remain {
-                            let offset = romaji.len() - remain;
-                            let next_nodes = self.terminal_nodes_for_prefix(&romaji[offset..]);
-                            for next_node in next_nodes {
-                                let next_entry = match self.mapping_entry(next_node) {
-                                    Some(entry) => entry,
-                                    None => continue,
-                                };
-                                if next_entry.remain == 0 {
-                                    self.value_trie
-                                        .get_key_into(entry.value_index as usize, &mut value_buffer);
-                                    let mut combined = value_buffer.clone();
-                                    self.value_trie
-                                        .get_key_into(next_entry.value_index as usize, &mut value_buffer);
-                                    combined.extend_from_slice(&value_buffer);
-                                    set.insert(combined);
-                                }
-                            }
-                        }
-                    } else {
-                        self.value_trie
-                            .get_key_into(entry.value_index as usize, &mut value_buffer);
-                        set.insert(value_buffer.clone());
-                    }
-                }
-                return RomajiPredictiveResult {
-                    prefix: hiragana,
-                    suffixes: set.into_iter().collect(),
-                };
-            }
-
             if let Some((node_index, match_len)) = last_found {
                 if let Some(entry) = self.mapping_entry(node_index) {
                     self.value_trie
@@ -192,6 +389,151 @@ impl RomajiProcessor {
             suffixes: vec![vec![]],
         }
     }
+
+    /// ローマ字の区切り方をすべて試し、`romaji_to_hiragana_predictively`と同じ形の結果を最大`limit`個返す
+    ///
+    /// 例えば`kanya`は「かにゃ」と「かんや」に、`tenin`は「てにん」と「てんいん」などになる。
+    /// 最初の結果は`romaji_to_hiragana_predictively`と同じで、同じ結果は一度だけ返す。
+    pub fn romaji_to_hiragana_segmentations(&self, romaji: &[u16], limit: usize) -> Vec<RomajiPredictiveResult> {
+        let mut results = Vec::new();
+        if romaji.is_empty() {
+            if limit > 0 {
+                results.push(RomajiPredictiveResult {
+                    prefix: vec![],
+                    suffixes: vec![vec![]],
+                });
+            }
+            return results;
+        }
+        let mut seen = HashSet::new();
+        self.segment(romaji, 0, &mut Vec::new(), &mut results, &mut seen, limit);
+        results
+    }
+
+    // `start`以降のローマ字を、一致する項目ごとに長い順に区切って変換する
+    fn segment(
+        &self,
+        romaji: &[u16],
+        start: usize,
+        hiragana: &mut Vec<u16>,
+        results: &mut Vec<RomajiPredictiveResult>,
+        seen: &mut HashSet<(Vec<u16>, Vec<Vec<u16>>)>,
+        limit: usize,
+    ) {
+        if results.len() >= limit {
+            return;
+        }
+        let suffixes = if start == romaji.len() {
+            Some(vec![vec![]])
+        } else {
+            self.predictive_suffixes(romaji, start)
+        };
+        if let Some(mut suffixes) = suffixes {
+            suffixes.sort();
+            if seen.insert((hiragana.clone(), suffixes.clone())) {
+                results.push(RomajiPredictiveResult {
+                    prefix: hiragana.clone(),
+                    suffixes,
+                });
+            }
+            return;
+        }
+
+        let query = &romaji[start..];
+        let mut matches: Vec<(usize, &MappingEntry)> = self
+            .key_trie
+            .common_prefix_search(query)
+            .enumerate()
+            .filter(|(_, node_index)| self.key_terminals.get(*node_index))
+            .filter_map(|(len, node_index)| self.mapping_entry(node_index).map(|entry| (len + 1, entry)))
+            .collect();
+        matches.reverse();
+        if matches.is_empty() {
+            hiragana.push(romaji[start]);
+            self.segment(romaji, start + 1, hiragana, results, seen, limit);
+            hiragana.pop();
+            return;
+        }
+        let mut value_buffer: Vec<u16> = Vec::with_capacity(4);
+        for (match_len, entry) in matches {
+            let end = hiragana.len();
+            self.value_trie
+                .get_key_into(entry.value_index as usize, &mut value_buffer);
+            hiragana.extend_from_slice(&value_buffer);
+            let next = start + match_len - entry.remain as usize;
+            self.segment(romaji, next, hiragana, results, seen, limit);
+            hiragana.truncate(end);
+            if results.len() >= limit {
+                return;
+            }
+        }
+    }
+
+    // `start`以降のローマ字が入力の途中で、複数の仮名になりうる場合は、その候補を返す
+    fn predictive_suffixes(&self, romaji: &[u16], start: usize) -> Option<Vec<Vec<u16>>> {
+        let terminal_nodes = self.terminal_nodes_for_prefix(&romaji[start..]);
+        if terminal_nodes.len() <= 1 {
+            return None;
+        }
+        let mut value_buffer: Vec<u16> = Vec::with_capacity(4);
+        let mut set: HashSet<Vec<u16>> = HashSet::new();
+        for node_index in terminal_nodes {
+            let entry = match self.mapping_entry(node_index) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if entry.remain > 0 {
+                let remain = entry.remain as usize;
+                if romaji.len() >= remain {
+                    let offset = romaji.len() - remain;
+                    let next_nodes = self.terminal_nodes_for_prefix(&romaji[offset..]);
+                    for next_node in next_nodes {
+                        let next_entry = match self.mapping_entry(next_node) {
+                            Some(entry) => entry,
+                            None => continue,
+                        };
+                        if next_entry.remain == 0 {
+                            self.value_trie
+                                .get_key_into(entry.value_index as usize, &mut value_buffer);
+                            let mut combined = value_buffer.clone();
+                            self.value_trie
+                                .get_key_into(next_entry.value_index as usize, &mut value_buffer);
+                            combined.extend_from_slice(&value_buffer);
+                            set.insert(combined);
+                        }
+                    }
+                }
+            } else {
+                self.value_trie
+                    .get_key_into(entry.value_index as usize, &mut value_buffer);
+                set.insert(value_buffer.clone());
+            }
+        }
+        Some(set.into_iter().collect())
+    }
+}
+
+/// Google日本語入力のローマ字テーブルの形式の変換表を、`RomajiProcessor::from_entries`に渡す形にする
+pub fn parse_romaji_table(text: &str) -> Result<Vec<(String, String, usize)>, RomajiTableError> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut columns = line.split('\t');
+        let (romaji, kana) = match (columns.next(), columns.next()) {
+            (Some(romaji), Some(kana)) if !romaji.is_empty() && !kana.is_empty() => (romaji, kana),
+            _ => return Err(RomajiTableError::MalformedLine { line: line_number }),
+        };
+        let next = columns.next().unwrap_or("");
+        if !romaji.ends_with(next) || next.len() == romaji.len() {
+            return Err(RomajiTableError::UnsupportedNextInput { line: line_number });
+        }
+        entries.push((romaji.to_string(), kana.to_string(), next.encode_utf16().count()));
+    }
+    Ok(entries)
 }
 
 pub fn build(roman_entries: &[(&str, &str, usize)]) {
@@ -435,4 +777,87 @@ mod tests {
     fn romaji_to_hiragana_predictively_w() {
         let (_, _) = romaji_to_hiragana_predictively("w");
     }
+
+    fn segmentations(romaji: &str, limit: usize) -> Vec<String> {
+        let processor = RomajiProcessor::new();
+        let romaji: Vec<u16> = romaji.encode_utf16().collect();
+        let mut readings = Vec::new();
+        for result in processor.romaji_to_hiragana_segmentations(&romaji, limit) {
+            for suffix in result.suffixes {
+                let mut reading = result.prefix.clone();
+                reading.extend(suffix);
+                readings.push(String::from_utf16(&reading).unwrap());
+            }
+        }
+        readings
+    }
+
+    #[test]
+    fn romaji_to_hiragana_segmentations_branches_on_n() {
+        assert_eq!(segmentations("kanya", 10), vec!["かにゃ", "かんや"]);
+        let readings = segmentations("tenin", 10);
+        assert!(readings.contains(&"てにん".to_string()));
+        assert!(readings.contains(&"てんいん".to_string()));
+        assert_eq!(segmentations("konnnichiha", 10)[0], "こんにちは");
+        assert!(segmentations("konnichiha", 10).contains(&"こんにちは".to_string()));
+    }
+
+    #[test]
+    fn romaji_to_hiragana_segmentations_starts_with_greedy_result() {
+        let processor = RomajiProcessor::new();
+        let romaji: Vec<u16> = "shinnyuu".encode_utf16().collect();
+        let greedy = processor.romaji_to_hiragana_predictively(&romaji);
+        let results = processor.romaji_to_hiragana_segmentations(&romaji, 1);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].prefix, greedy.prefix);
+        assert_eq!(segmentations("kiku", 10), vec!["きく"]);
+    }
+
+    #[test]
+    fn from_table_reads_google_ime_format() {
+        // AZIKの「kz」(かん) と「;」(っ) のように、既定の表にない入力を使う
+        let table = "# AZIKの一部\nka\tか\nkz\tかん\nn\tん\nta\tた\ntt\tっ\tt\n;\tっ\n";
+        let processor = RomajiProcessor::from_table(table).unwrap();
+        assert_eq!(processor.romaji_to_hiragana("kzta"), "かんた");
+        assert_eq!(processor.romaji_to_hiragana("ttaka;"), "ったかっ");
+        let kz: Vec<u16> = "kz".encode_utf16().collect();
+        let result = processor.romaji_to_hiragana_predictively(&kz);
+        assert_eq!(String::from_utf16(&result.prefix).unwrap(), "かん");
+    }
+
+    #[test]
+    fn kana_to_romaji_round_trips() {
+        let processor = RomajiProcessor::new();
+        assert_eq!(processor.kana_to_romaji("けんさく").as_deref(), Some("kensaku"));
+        assert_eq!(processor.kana_to_romaji("しんいち").as_deref(), Some("sinniti"));
+        assert_eq!(processor.kana_to_romaji("ガッコウ").as_deref(), Some("gakkou"));
+        assert_eq!(processor.kana_to_romaji("きゃっ").as_deref(), Some("kyaxtu"));
+        assert_eq!(
+            processor.kana_to_romaji("第2版のしょうさい").as_deref(),
+            Some("第2版noshousai")
+        );
+        let all = processor.kana_to_romaji_all("ちゃ", 10);
+        assert!(all.contains(&"tya".to_string()) && all.contains(&"cha".to_string()));
+        for romaji in &all {
+            assert_eq!(processor.romaji_to_hiragana(romaji), "ちゃ");
+        }
+    }
+
+    #[test]
+    fn from_table_reports_invalid_lines() {
+        assert_eq!(
+            RomajiProcessor::from_table("ka\tか\nki\n").err(),
+            Some(RomajiTableError::MalformedLine { line: 2 })
+        );
+        assert_eq!(
+            RomajiProcessor::from_table("kk\tっ\tt\n").err(),
+            Some(RomajiTableError::UnsupportedNextInput { line: 1 })
+        );
+        assert_eq!(
+            RomajiProcessor::from_entries(&[("k", "っ", 1)]).err(),
+            Some(RomajiTableError::InvalidEntry {
+                romaji: "k".to_string()
+            })
+        );
+    }
 }
@@ -0,0 +1,804 @@
+use super::romaji_processor::RomajiProcessor;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// ローマ字の入力方式
+///
+/// `Default`以外は、`RomajiProcessor::from_entries`で変換表を組み立てる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RomajiScheme {
+    /// Google日本語入力などの一般的なローマ字入力。`DEFAULT_ROMAJI_ENTRIES`と同じ。
+    #[default]
+    Default,
+    /// AZIK。一般的なローマ字入力に、撥音拡張 (`kz`→かん)、二重母音拡張 (`kq`→かい)、
+    /// `;`→っ、`q`→ん、`x`→sh、`:`→ーを加える。`kt`→ことのような単語の省略入力は含まない。
+    Azik,
+    /// Dvorak配列向けのACT。か行を`c`で打ち、母音キー (`a o e u i`) の下の段 (`; q j k x`) で撥音、
+    /// 上の段 (`' , . p`) で二重母音 (あい、おう、えい、うう) を打つ。一般的なローマ字もそのまま打てる。
+    Act,
+    /// 訓令式 (ISO 3602の厳格式)。ぢ、づ、をは`di`、`du`、`wo`で打つ。
+    Kunrei,
+    /// ヘボン式 (修正ヘボン式)。ぢ、づはじ、ずと区別せず、をは打てない。
+    Hepburn,
+}
+
+impl FromStr for RomajiScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RomajiScheme, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "default" => Ok(RomajiScheme::Default),
+            "azik" => Ok(RomajiScheme::Azik),
+            "act" => Ok(RomajiScheme::Act),
+            "kunrei" => Ok(RomajiScheme::Kunrei),
+            "hepburn" => Ok(RomajiScheme::Hepburn),
+            _ => Err(format!("unsupported romaji scheme: {}", s)),
+        }
+    }
+}
+
+impl RomajiScheme {
+    /// `RomajiProcessor::from_entries`に渡す変換表
+    pub fn entries(self) -> Vec<(String, String, usize)> {
+        match self {
+            RomajiScheme::Default => to_owned_entries(&DEFAULT_ROMAJI_ENTRIES),
+            RomajiScheme::Azik => extend_default(&AZIK_CONSONANTS, &AZIK_EXTENSIONS, &AZIK_KEYS),
+            RomajiScheme::Act => extend_default(&ACT_CONSONANTS, &ACT_EXTENSIONS, &ACT_KEYS),
+            RomajiScheme::Kunrei => strict_entries(&KUNREI_SYLLABLES, &[]),
+            RomajiScheme::Hepburn => strict_entries(&HEPBURN_SYLLABLES, &[("tc", "っ", 1)]),
+        }
+    }
+
+    pub fn processor(self) -> RomajiProcessor {
+        match self {
+            // 組み込みの変換表は、あらかじめ構築したものを使う
+            RomajiScheme::Default => RomajiProcessor::new(),
+            _ => RomajiProcessor::from_entries(&self.entries()).expect("built-in romaji table is valid"),
+        }
+    }
+}
+
+fn to_owned_entries(entries: &[(&str, &str, usize)]) -> Vec<(String, String, usize)> {
+    entries
+        .iter()
+        .map(|(romaji, kana, remain)| (romaji.to_string(), kana.to_string(), *remain))
+        .collect()
+}
+
+// 同じローマ字は後のものを残し、ローマ字の順に並べる
+fn overwrite(entries: Vec<(String, String, usize)>) -> Vec<(String, String, usize)> {
+    let table: BTreeMap<String, (String, usize)> = entries
+        .into_iter()
+        .map(|(romaji, kana, remain)| (romaji, (kana, remain)))
+        .collect();
+    table
+        .into_iter()
+        .map(|(romaji, (kana, remain))| (romaji, kana, remain))
+        .collect()
+}
+
+// 一般的なローマ字入力に`keys`を加え、`consonants`の各子音と拡張キーで、仮名と続く音を打てるようにする
+fn extend_default(
+    consonants: &[&str],
+    extensions: &[(&str, usize, &str)],
+    keys: &[(&str, &str, usize)],
+) -> Vec<(String, String, usize)> {
+    let mut entries = to_owned_entries(&DEFAULT_ROMAJI_ENTRIES);
+    entries.extend(to_owned_entries(keys));
+    let mut table: BTreeMap<String, String> = BTreeMap::new();
+    for (romaji, kana, _) in &entries {
+        table.insert(romaji.clone(), kana.clone());
+    }
+    let mut extended = Vec::new();
+    for consonant in consonants {
+        for (key, vowel, suffix) in extensions {
+            let vowel = ["a", "i", "u", "e", "o"][*vowel];
+            if let Some(kana) = table.get(&format!("{}{}", consonant, vowel)) {
+                extended.push((format!("{}{}", consonant, key), format!("{}{}", kana, suffix), 0));
+            }
+        }
+    }
+    entries.extend(extended);
+    overwrite(entries)
+}
+
+// 音節の一覧に、撥音と促音を加える
+fn strict_entries(syllables: &[(&str, &str)], extra: &[(&str, &str, usize)]) -> Vec<(String, String, usize)> {
+    let mut entries: Vec<(String, String, usize)> = syllables
+        .iter()
+        .map(|(romaji, kana)| (romaji.to_string(), kana.to_string(), 0))
+        .collect();
+    entries.push(("n".to_string(), "ん".to_string(), 0));
+    entries.push(("n'".to_string(), "ん".to_string(), 0));
+    entries.push(("-".to_string(), "ー".to_string(), 0));
+    // 子音を重ねると促音になり、2つ目の子音は次の音節に使う
+    for consonant in ["k", "s", "t", "g", "z", "d", "b", "p"] {
+        entries.push((format!("{}{}", consonant, consonant), "っ".to_string(), 1));
+    }
+    entries.extend(to_owned_entries(extra));
+    overwrite(entries)
+}
+
+// 撥音拡張と二重母音拡張を使える子音。`x`はAZIKでのshを表す。
+const AZIK_CONSONANTS: [&str; 27] = [
+    "k", "s", "t", "n", "h", "m", "y", "r", "w", "g", "z", "d", "b", "p", "f", "j", "x", "ky", "sy", "ty", "ny", "hy",
+    "my", "ry", "gy", "by", "py",
+];
+
+// (拡張キー, 母音の番号, 続く音)。母音の番号は`a i u e o`の順。
+const AZIK_EXTENSIONS: [(&str, usize, &str); 9] = [
+    ("z", 0, "ん"),
+    ("k", 1, "ん"),
+    ("j", 2, "ん"),
+    ("d", 3, "ん"),
+    ("l", 4, "ん"),
+    ("q", 0, "い"),
+    ("h", 2, "う"),
+    ("w", 3, "い"),
+    ("p", 4, "う"),
+];
+
+const AZIK_KEYS: [(&str, &str, usize); 8] = [
+    (";", "っ", 0),
+    ("q", "ん", 0),
+    (":", "ー", 0),
+    ("xa", "しゃ", 0),
+    ("xi", "し", 0),
+    ("xu", "しゅ", 0),
+    ("xe", "しぇ", 0),
+    ("xo", "しょ", 0),
+];
+
+// ACTでは、か行を`k`の代わりに`c`で打つ
+const ACT_CONSONANTS: [&str; 26] = [
+    "c", "s", "t", "n", "h", "m", "y", "r", "w", "g", "z", "d", "b", "p", "f", "j", "cy", "sy", "ty", "ny", "hy", "my",
+    "ry", "gy", "by", "py",
+];
+
+// Dvorak配列で、母音キー (`a o e u i`) の下の段で撥音、上の段で二重母音を打つ
+const ACT_EXTENSIONS: [(&str, usize, &str); 9] = [
+    (";", 0, "ん"),
+    ("x", 1, "ん"),
+    ("k", 2, "ん"),
+    ("j", 3, "ん"),
+    ("q", 4, "ん"),
+    ("'", 0, "い"),
+    ("p", 2, "う"),
+    (".", 3, "い"),
+    (",", 4, "う"),
+];
+
+const ACT_KEYS: [(&str, &str, usize); 10] = [
+    ("ca", "か", 0),
+    ("ci", "き", 0),
+    ("cu", "く", 0),
+    ("ce", "け", 0),
+    ("co", "こ", 0),
+    ("cya", "きゃ", 0),
+    ("cyi", "きぃ", 0),
+    ("cyu", "きゅ", 0),
+    ("cye", "きぇ", 0),
+    ("cyo", "きょ", 0),
+];
+
+// 訓令式の音節 (ISO 3602の厳格式)
+const KUNREI_SYLLABLES: [(&str, &str); 106] = [
+    ("a", "あ"),
+    ("i", "い"),
+    ("u", "う"),
+    ("e", "え"),
+    ("o", "お"),
+    ("ka", "か"),
+    ("ki", "き"),
+    ("ku", "く"),
+    ("ke", "け"),
+    ("ko", "こ"),
+    ("sa", "さ"),
+    ("si", "し"),
+    ("su", "す"),
+    ("se", "せ"),
+    ("so", "そ"),
+    ("ta", "た"),
+    ("ti", "ち"),
+    ("tu", "つ"),
+    ("te", "て"),
+    ("to", "と"),
+    ("na", "な"),
+    ("ni", "に"),
+    ("nu", "ぬ"),
+    ("ne", "ね"),
+    ("no", "の"),
+    ("ha", "は"),
+    ("hi", "ひ"),
+    ("hu", "ふ"),
+    ("he", "へ"),
+    ("ho", "ほ"),
+    ("ma", "ま"),
+    ("mi", "み"),
+    ("mu", "む"),
+    ("me", "め"),
+    ("mo", "も"),
+    ("ya", "や"),
+    ("yu", "ゆ"),
+    ("yo", "よ"),
+    ("ra", "ら"),
+    ("ri", "り"),
+    ("ru", "る"),
+    ("re", "れ"),
+    ("ro", "ろ"),
+    ("wa", "わ"),
+    ("wo", "を"),
+    ("ga", "が"),
+    ("gi", "ぎ"),
+    ("gu", "ぐ"),
+    ("ge", "げ"),
+    ("go", "ご"),
+    ("za", "ざ"),
+    ("zi", "じ"),
+    ("zu", "ず"),
+    ("ze", "ぜ"),
+    ("zo", "ぞ"),
+    ("da", "だ"),
+    ("di", "ぢ"),
+    ("du", "づ"),
+    ("de", "で"),
+    ("do", "ど"),
+    ("ba", "ば"),
+    ("bi", "び"),
+    ("bu", "ぶ"),
+    ("be", "べ"),
+    ("bo", "ぼ"),
+    ("pa", "ぱ"),
+    ("pi", "ぴ"),
+    ("pu", "ぷ"),
+    ("pe", "ぺ"),
+    ("po", "ぽ"),
+    ("kya", "きゃ"),
+    ("kyu", "きゅ"),
+    ("kyo", "きょ"),
+    ("sya", "しゃ"),
+    ("syu", "しゅ"),
+    ("syo", "しょ"),
+    ("tya", "ちゃ"),
+    ("tyu", "ちゅ"),
+    ("tyo", "ちょ"),
+    ("nya", "にゃ"),
+    ("nyu", "にゅ"),
+    ("nyo", "にょ"),
+    ("hya", "ひゃ"),
+    ("hyu", "ひゅ"),
+    ("hyo", "ひょ"),
+    ("mya", "みゃ"),
+    ("myu", "みゅ"),
+    ("myo", "みょ"),
+    ("rya", "りゃ"),
+    ("ryu", "りゅ"),
+    ("ryo", "りょ"),
+    ("gya", "ぎゃ"),
+    ("gyu", "ぎゅ"),
+    ("gyo", "ぎょ"),
+    ("zya", "じゃ"),
+    ("zyu", "じゅ"),
+    ("zyo", "じょ"),
+    ("dya", "ぢゃ"),
+    ("dyu", "ぢゅ"),
+    ("dyo", "ぢょ"),
+    ("bya", "びゃ"),
+    ("byu", "びゅ"),
+    ("byo", "びょ"),
+    ("pya", "ぴゃ"),
+    ("pyu", "ぴゅ"),
+    ("pyo", "ぴょ"),
+];
+
+// 修正ヘボン式の音節
+const HEPBURN_SYLLABLES: [(&str, &str); 100] = [
+    ("a", "あ"),
+    ("i", "い"),
+    ("u", "う"),
+    ("e", "え"),
+    ("o", "お"),
+    ("ka", "か"),
+    ("ki", "き"),
+    ("ku", "く"),
+    ("ke", "け"),
+    ("ko", "こ"),
+    ("sa", "さ"),
+    ("shi", "し"),
+    ("su", "す"),
+    ("se", "せ"),
+    ("so", "そ"),
+    ("ta", "た"),
+    ("chi", "ち"),
+    ("tsu", "つ"),
+    ("te", "て"),
+    ("to", "と"),
+    ("na", "な"),
+    ("ni", "に"),
+    ("nu", "ぬ"),
+    ("ne", "ね"),
+    ("no", "の"),
+    ("ha", "は"),
+    ("hi", "ひ"),
+    ("fu", "ふ"),
+    ("he", "へ"),
+    ("ho", "ほ"),
+    ("ma", "ま"),
+    ("mi", "み"),
+    ("mu", "む"),
+    ("me", "め"),
+    ("mo", "も"),
+    ("ya", "や"),
+    ("yu", "ゆ"),
+    ("yo", "よ"),
+    ("ra", "ら"),
+    ("ri", "り"),
+    ("ru", "る"),
+    ("re", "れ"),
+    ("ro", "ろ"),
+    ("wa", "わ"),
+    ("ga", "が"),
+    ("gi", "ぎ"),
+    ("gu", "ぐ"),
+    ("ge", "げ"),
+    ("go", "ご"),
+    ("za", "ざ"),
+    ("ji", "じ"),
+    ("zu", "ず"),
+    ("ze", "ぜ"),
+    ("zo", "ぞ"),
+    ("da", "だ"),
+    ("de", "で"),
+    ("do", "ど"),
+    ("ba", "ば"),
+    ("bi", "び"),
+    ("bu", "ぶ"),
+    ("be", "べ"),
+    ("bo", "ぼ"),
+    ("pa", "ぱ"),
+    ("pi", "ぴ"),
+    ("pu", "ぷ"),
+    ("pe", "ぺ"),
+    ("po", "ぽ"),
+    ("kya", "きゃ"),
+    ("kyu", "きゅ"),
+    ("kyo", "きょ"),
+    ("sha", "しゃ"),
+    ("shu", "しゅ"),
+    ("sho", "しょ"),
+    ("cha", "ちゃ"),
+    ("chu", "ちゅ"),
+    ("cho", "ちょ"),
+    ("nya", "にゃ"),
+    ("nyu", "にゅ"),
+    ("nyo", "にょ"),
+    ("hya", "ひゃ"),
+    ("hyu", "ひゅ"),
+    ("hyo", "ひょ"),
+    ("mya", "みゃ"),
+    ("myu", "みゅ"),
+    ("myo", "みょ"),
+    ("rya", "りゃ"),
+    ("ryu", "りゅ"),
+    ("ryo", "りょ"),
+    ("gya", "ぎゃ"),
+    ("gyu", "ぎゅ"),
+    ("gyo", "ぎょ"),
+    ("ja", "じゃ"),
+    ("ju", "じゅ"),
+    ("jo", "じょ"),
+    ("bya", "びゃ"),
+    ("byu", "びゅ"),
+    ("byo", "びょ"),
+    ("pya", "ぴゃ"),
+    ("pyu", "ぴゅ"),
+    ("pyo", "ぴょ"),
+];
+
+/// 一般的なローマ字入力の変換表。組み込みの変換表は、これを`build`で構築したもの。
+pub const DEFAULT_ROMAJI_ENTRIES: [(&str, &str, usize); 312] = [
+    ("-", "ー", 0),
+    ("~", "〜", 0),
+    (".", "。", 0),
+    (",", "、", 0),
+    ("z/", "・", 0),
+    ("z.", "…", 0),
+    ("z,", "‥", 0),
+    ("zh", "←", 0),
+    ("zj", "↓", 0),
+    ("zk", "↑", 0),
+    ("zl", "→", 0),
+    ("z-", "〜", 0),
+    ("z[", "『", 0),
+    ("z]", "』", 0),
+    ("[", "「", 0),
+    ("]", "」", 0),
+    ("va", "ゔぁ", 0),
+    ("vi", "ゔぃ", 0),
+    ("vu", "ゔ", 0),
+    ("ve", "ゔぇ", 0),
+    ("vo", "ゔぉ", 0),
+    ("vya", "ゔゃ", 0),
+    ("vyi", "ゔぃ", 0),
+    ("vyu", "ゔゅ", 0),
+    ("vye", "ゔぇ", 0),
+    ("vyo", "ゔょ", 0),
+    ("qq", "っ", 1),
+    ("vv", "っ", 1),
+    ("ll", "っ", 1),
+    ("xx", "っ", 1),
+    ("kk", "っ", 1),
+    ("gg", "っ", 1),
+    ("ss", "っ", 1),
+    ("zz", "っ", 1),
+    ("jj", "っ", 1),
+    ("tt", "っ", 1),
+    ("dd", "っ", 1),
+    ("hh", "っ", 1),
+    ("ff", "っ", 1),
+    ("bb", "っ", 1),
+    ("pp", "っ", 1),
+    ("mm", "っ", 1),
+    ("yy", "っ", 1),
+    ("rr", "っ", 1),
+    ("ww", "っ", 1),
+    ("www", "w", 2),
+    ("cc", "っ", 1),
+    ("kya", "きゃ", 0),
+    ("kyi", "きぃ", 0),
+    ("kyu", "きゅ", 0),
+    ("kye", "きぇ", 0),
+    ("kyo", "きょ", 0),
+    ("gya", "ぎゃ", 0),
+    ("gyi", "ぎぃ", 0),
+    ("gyu", "ぎゅ", 0),
+    ("gye", "ぎぇ", 0),
+    ("gyo", "ぎょ", 0),
+    ("sya", "しゃ", 0),
+    ("syi", "しぃ", 0),
+    ("syu", "しゅ", 0),
+    ("sye", "しぇ", 0),
+    ("syo", "しょ", 0),
+    ("sha", "しゃ", 0),
+    ("shi", "し", 0),
+    ("shu", "しゅ", 0),
+    ("she", "しぇ", 0),
+    ("sho", "しょ", 0),
+    ("zya", "じゃ", 0),
+    ("zyi", "じぃ", 0),
+    ("zyu", "じゅ", 0),
+    ("zye", "じぇ", 0),
+    ("zyo", "じょ", 0),
+    ("tya", "ちゃ", 0),
+    ("tyi", "ちぃ", 0),
+    ("tyu", "ちゅ", 0),
+    ("tye", "ちぇ", 0),
+    ("tyo", "ちょ", 0),
+    ("cha", "ちゃ", 0),
+    ("chi", "ち", 0),
+    ("chu", "ちゅ", 0),
+    ("che", "ちぇ", 0),
+    ("cho", "ちょ", 0),
+    ("cya", "ちゃ", 0),
+    ("cyi", "ちぃ", 0),
+    ("cyu", "ちゅ", 0),
+    ("cye", "ちぇ", 0),
+    ("cyo", "ちょ", 0),
+    ("dya", "ぢゃ", 0),
+    ("dyi", "ぢぃ", 0),
+    ("dyu", "ぢゅ", 0),
+    ("dye", "ぢぇ", 0),
+    ("dyo", "ぢょ", 0),
+    ("tsa", "つぁ", 0),
+    ("tsi", "つぃ", 0),
+    ("tse", "つぇ", 0),
+    ("tso", "つぉ", 0),
+    ("tha", "てゃ", 0),
+    ("thi", "てぃ", 0),
+    ("t'i", "てぃ", 0),
+    ("thu", "てゅ", 0),
+    ("the", "てぇ", 0),
+    ("tho", "てょ", 0),
+    ("t'yu", "てゅ", 0),
+    ("dha", "でゃ", 0),
+    ("dhi", "でぃ", 0),
+    ("d'i", "でぃ", 0),
+    ("dhu", "でゅ", 0),
+    ("dhe", "でぇ", 0),
+    ("dho", "でょ", 0),
+    ("d'yu", "でゅ", 0),
+    ("twa", "とぁ", 0),
+    ("twi", "とぃ", 0),
+    ("twu", "とぅ", 0),
+    ("twe", "とぇ", 0),
+    ("two", "とぉ", 0),
+    ("t'u", "とぅ", 0),
+    ("dwa", "どぁ", 0),
+    ("dwi", "どぃ", 0),
+    ("dwu", "どぅ", 0),
+    ("dwe", "どぇ", 0),
+    ("dwo", "どぉ", 0),
+    ("d'u", "どぅ", 0),
+    ("nya", "にゃ", 0),
+    ("nyi", "にぃ", 0),
+    ("nyu", "にゅ", 0),
+    ("nye", "にぇ", 0),
+    ("nyo", "にょ", 0),
+    ("hya", "ひゃ", 0),
+    ("hyi", "ひぃ", 0),
+    ("hyu", "ひゅ", 0),
+    ("hye", "ひぇ", 0),
+    ("hyo", "ひょ", 0),
+    ("bya", "びゃ", 0),
+    ("byi", "びぃ", 0),
+    ("byu", "びゅ", 0),
+    ("bye", "びぇ", 0),
+    ("byo", "びょ", 0),
+    ("pya", "ぴゃ", 0),
+    ("pyi", "ぴぃ", 0),
+    ("pyu", "ぴゅ", 0),
+    ("pye", "ぴぇ", 0),
+    ("pyo", "ぴょ", 0),
+    ("fa", "ふぁ", 0),
+    ("fi", "ふぃ", 0),
+    ("fu", "ふ", 0),
+    ("fe", "ふぇ", 0),
+    ("fo", "ふぉ", 0),
+    ("fya", "ふゃ", 0),
+    ("fyu", "ふゅ", 0),
+    ("fyo", "ふょ", 0),
+    ("hwa", "ふぁ", 0),
+    ("hwi", "ふぃ", 0),
+    ("hwe", "ふぇ", 0),
+    ("hwo", "ふぉ", 0),
+    ("hwyu", "ふゅ", 0),
+    ("mya", "みゃ", 0),
+    ("myi", "みぃ", 0),
+    ("myu", "みゅ", 0),
+    ("mye", "みぇ", 0),
+    ("myo", "みょ", 0),
+    ("rya", "りゃ", 0),
+    ("ryi", "りぃ", 0),
+    ("ryu", "りゅ", 0),
+    ("rye", "りぇ", 0),
+    ("ryo", "りょ", 0),
+    ("n'", "ん", 0),
+    ("nn", "ん", 0),
+    ("n", "ん", 0),
+    ("xn", "ん", 0),
+    ("a", "あ", 0),
+    ("i", "い", 0),
+    ("u", "う", 0),
+    ("wu", "う", 0),
+    ("e", "え", 0),
+    ("o", "お", 0),
+    ("xa", "ぁ", 0),
+    ("xi", "ぃ", 0),
+    ("xu", "ぅ", 0),
+    ("xe", "ぇ", 0),
+    ("xo", "ぉ", 0),
+    ("la", "ぁ", 0),
+    ("li", "ぃ", 0),
+    ("lu", "ぅ", 0),
+    ("le", "ぇ", 0),
+    ("lo", "ぉ", 0),
+    ("lyi", "ぃ", 0),
+    ("xyi", "ぃ", 0),
+    ("lye", "ぇ", 0),
+    ("xye", "ぇ", 0),
+    ("ye", "いぇ", 0),
+    ("ka", "か", 0),
+    ("ki", "き", 0),
+    ("ku", "く", 0),
+    ("ke", "け", 0),
+    ("ko", "こ", 0),
+    ("xka", "ヵ", 0),
+    ("xke", "ヶ", 0),
+    ("lka", "ヵ", 0),
+    ("lke", "ヶ", 0),
+    ("ga", "が", 0),
+    ("gi", "ぎ", 0),
+    ("gu", "ぐ", 0),
+    ("ge", "げ", 0),
+    ("go", "ご", 0),
+    ("sa", "さ", 0),
+    ("si", "し", 0),
+    ("su", "す", 0),
+    ("se", "せ", 0),
+    ("so", "そ", 0),
+    ("ca", "か", 0),
+    ("ci", "し", 0),
+    ("cu", "く", 0),
+    ("ce", "せ", 0),
+    ("co", "こ", 0),
+    ("qa", "くぁ", 0),
+    ("qi", "くぃ", 0),
+    ("qu", "く", 0),
+    ("qe", "くぇ", 0),
+    ("qo", "くぉ", 0),
+    ("kwa", "くぁ", 0),
+    ("kwi", "くぃ", 0),
+    ("kwu", "くぅ", 0),
+    ("kwe", "くぇ", 0),
+    ("kwo", "くぉ", 0),
+    ("gwa", "ぐぁ", 0),
+    ("gwi", "ぐぃ", 0),
+    ("gwu", "ぐぅ", 0),
+    ("gwe", "ぐぇ", 0),
+    ("gwo", "ぐぉ", 0),
+    ("za", "ざ", 0),
+    ("zi", "じ", 0),
+    ("zu", "ず", 0),
+    ("ze", "ぜ", 0),
+    ("zo", "ぞ", 0),
+    ("ja", "じゃ", 0),
+    ("ji", "じ", 0),
+    ("ju", "じゅ", 0),
+    ("je", "じぇ", 0),
+    ("jo", "じょ", 0),
+    ("jya", "じゃ", 0),
+    ("jyi", "じぃ", 0),
+    ("jyu", "じゅ", 0),
+    ("jye", "じぇ", 0),
+    ("jyo", "じょ", 0),
+    ("ta", "た", 0),
+    ("ti", "ち", 0),
+    ("tu", "つ", 0),
+    ("tsu", "つ", 0),
+    ("te", "て", 0),
+    ("to", "と", 0),
+    ("da", "だ", 0),
+    ("di", "ぢ", 0),
+    ("du", "づ", 0),
+    ("de", "で", 0),
+    ("do", "ど", 0),
+    ("xtu", "っ", 0),
+    ("xtsu", "っ", 0),
+    ("ltu", "っ", 0),
+    ("ltsu", "っ", 0),
+    ("na", "な", 0),
+    ("ni", "に", 0),
+    ("nu", "ぬ", 0),
+    ("ne", "ね", 0),
+    ("no", "の", 0),
+    ("ha", "は", 0),
+    ("hi", "ひ", 0),
+    ("hu", "ふ", 0),
+    ("fu", "ふ", 0),
+    ("he", "へ", 0),
+    ("ho", "ほ", 0),
+    ("ba", "ば", 0),
+    ("bi", "び", 0),
+    ("bu", "ぶ", 0),
+    ("be", "べ", 0),
+    ("bo", "ぼ", 0),
+    ("pa", "ぱ", 0),
+    ("pi", "ぴ", 0),
+    ("pu", "ぷ", 0),
+    ("pe", "ぺ", 0),
+    ("po", "ぽ", 0),
+    ("ma", "ま", 0),
+    ("mi", "み", 0),
+    ("mu", "む", 0),
+    ("me", "め", 0),
+    ("mo", "も", 0),
+    ("xya", "ゃ", 0),
+    ("lya", "ゃ", 0),
+    ("ya", "や", 0),
+    ("wyi", "ゐ", 0),
+    ("xyu", "ゅ", 0),
+    ("lyu", "ゅ", 0),
+    ("yu", "ゆ", 0),
+    ("wye", "ゑ", 0),
+    ("xyo", "ょ", 0),
+    ("lyo", "ょ", 0),
+    ("yo", "よ", 0),
+    ("ra", "ら", 0),
+    ("ri", "り", 0),
+    ("ru", "る", 0),
+    ("re", "れ", 0),
+    ("ro", "ろ", 0),
+    ("xwa", "ゎ", 0),
+    ("lwa", "ゎ", 0),
+    ("wa", "わ", 0),
+    ("wi", "うぃ", 0),
+    ("we", "うぇ", 0),
+    ("wo", "を", 0),
+    ("wha", "うぁ", 0),
+    ("whi", "うぃ", 0),
+    ("whu", "う", 0),
+    ("whe", "うぇ", 0),
+    ("who", "うぉ", 0),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(scheme: RomajiScheme, romaji: &str) -> String {
+        scheme.processor().romaji_to_hiragana(romaji)
+    }
+
+    #[test]
+    fn default_entries_match_built_in_table() {
+        let processor = RomajiProcessor::from_entries(&RomajiScheme::Default.entries()).unwrap();
+        for romaji in ["kensaku", "atti", "att", "www", "kk", "n", "shinbun", "z/", "vyu"] {
+            assert_eq!(
+                processor.romaji_to_hiragana(romaji),
+                convert(RomajiScheme::Default, romaji)
+            );
+        }
+    }
+
+    #[test]
+    fn azik_extensions() {
+        assert_eq!(convert(RomajiScheme::Azik, "kzsp"), "かんそう");
+        assert_eq!(convert(RomajiScheme::Azik, "kk"), "きん");
+        assert_eq!(convert(RomajiScheme::Azik, "ga;ko"), "がっこ");
+        assert_eq!(convert(RomajiScheme::Azik, "xpbq"), "しょうばい");
+        assert_eq!(convert(RomajiScheme::Azik, "tq:"), "たいー");
+        // 一般的なローマ字もそのまま打てる
+        assert_eq!(convert(RomajiScheme::Azik, "kensaku"), "けんさく");
+    }
+
+    #[test]
+    fn act_extensions() {
+        // か行は`c`で打つ
+        assert_eq!(convert(RomajiScheme::Act, "cacicucecocya"), "かきくけこきゃ");
+        // 撥音拡張: 母音キー`a o e u i`の下の段`; q j k x`
+        assert_eq!(convert(RomajiScheme::Act, "c;"), "かん");
+        assert_eq!(convert(RomajiScheme::Act, "cq"), "こん");
+        assert_eq!(convert(RomajiScheme::Act, "cj"), "けん");
+        assert_eq!(convert(RomajiScheme::Act, "ck"), "くん");
+        assert_eq!(convert(RomajiScheme::Act, "cx"), "きん");
+        // 二重母音拡張: 母音キーの上の段`' , . p`
+        assert_eq!(convert(RomajiScheme::Act, "c'"), "かい");
+        assert_eq!(convert(RomajiScheme::Act, "c,"), "こう");
+        assert_eq!(convert(RomajiScheme::Act, "c."), "けい");
+        assert_eq!(convert(RomajiScheme::Act, "cp"), "くう");
+        assert_eq!(convert(RomajiScheme::Act, "c;s,"), "かんそう");
+        assert_eq!(convert(RomajiScheme::Act, "sy,"), "しょう");
+        assert_eq!(convert(RomajiScheme::Act, "tjs'"), "てんさい");
+        // 一般的なローマ字もそのまま打てる
+        assert_eq!(convert(RomajiScheme::Act, "kensaku"), "けんさく");
+    }
+
+    #[test]
+    fn syllable_tables_have_no_duplicates() {
+        for syllables in [&KUNREI_SYLLABLES[..], &HEPBURN_SYLLABLES[..]] {
+            let mut romaji: Vec<&str> = syllables.iter().map(|(romaji, _)| *romaji).collect();
+            romaji.sort_unstable();
+            romaji.dedup();
+            assert_eq!(romaji.len(), syllables.len());
+        }
+    }
+
+    #[test]
+    fn strict_schemes() {
+        assert_eq!(convert(RomajiScheme::Kunrei, "sinbun"), "しんぶん");
+        assert_eq!(convert(RomajiScheme::Kunrei, "tyotto"), "ちょっと");
+        assert_eq!(convert(RomajiScheme::Kunrei, "hanadi"), "はなぢ");
+        assert_eq!(convert(RomajiScheme::Kunrei, "shi"), "sひ");
+        assert_eq!(convert(RomajiScheme::Hepburn, "shinbun"), "しんぶん");
+        assert_eq!(convert(RomajiScheme::Hepburn, "matcha"), "まっちゃ");
+        assert_eq!(convert(RomajiScheme::Hepburn, "si"), "sい");
+    }
+
+    #[test]
+    fn kana_to_romaji_by_scheme() {
+        let hepburn = RomajiScheme::Hepburn.processor();
+        assert_eq!(hepburn.kana_to_romaji("しんぶん").as_deref(), Some("shinbun"));
+        assert_eq!(hepburn.kana_to_romaji("きんいろ").as_deref(), Some("kin'iro"));
+        assert_eq!(hepburn.kana_to_romaji("マッチャ").as_deref(), Some("matcha"));
+        let kunrei = RomajiScheme::Kunrei.processor();
+        assert_eq!(kunrei.kana_to_romaji("ちょっと").as_deref(), Some("tyotto"));
+        assert_eq!(kunrei.kana_to_romaji("ふじさん").as_deref(), Some("huzisan"));
+    }
+
+    #[test]
+    fn parse_scheme_names() {
+        assert_eq!("AZIK".parse(), Ok(RomajiScheme::Azik));
+        assert_eq!("hepburn".parse(), Ok(RomajiScheme::Hepburn));
+        assert!("qwerty".parse::<RomajiScheme>().is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use super::dictionary::Dictionary;
+use super::query::{QueryOptions, query_with_options};
+use super::regex_generator::RegexOperator;
+use regex::Regex;
+use regex_automata::dfa;
+
+/// 検索語から、`regex`クレートの正規表現を生成する
+///
+/// `RegexOperator::RustNonNewline`で生成するため、エスケープの誤りでコンパイルに失敗することはない。
+/// 候補が非常に多く、正規表現のサイズの上限を超えた場合にだけエラーを返す。
+pub fn query_regex<D: Dictionary + ?Sized>(word: &str, dict: &D) -> Result<Regex, regex::Error> {
+    query_regex_with_options(word, dict, &RegexOperator::RustNonNewline, &QueryOptions::default())
+}
+
+/// 設定に従って、検索語から`regex`クレートの正規表現を生成する
+///
+/// `operator`には`RegexOperator::Rust`か`RegexOperator::RustNonNewline`を指定する。
+pub fn query_regex_with_options<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+    operator: &RegexOperator,
+    options: &QueryOptions,
+) -> Result<Regex, regex::Error> {
+    let pattern = query_with_options(word.to_string(), dict, operator, options);
+    Regex::new(&pattern)
+}
+
+/// 検索語から、`regex-automata`のDFAによる正規表現を生成する
+///
+/// 構築には時間がかかるが、同じパターンで大量のテキストを検索する場合は`Regex`より速い。
+pub fn query_dfa<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+) -> Result<dfa::regex::Regex, Box<dfa::dense::BuildError>> {
+    query_dfa_with_options(word, dict, &RegexOperator::RustNonNewline, &QueryOptions::default())
+}
+
+/// 設定に従って、検索語から`regex-automata`のDFAによる正規表現を生成する
+pub fn query_dfa_with_options<D: Dictionary + ?Sized>(
+    word: &str,
+    dict: &D,
+    operator: &RegexOperator,
+    options: &QueryOptions,
+) -> Result<dfa::regex::Regex, Box<dfa::dense::BuildError>> {
+    let pattern = query_with_options(word.to_string(), dict, operator, options);
+    dfa::regex::Regex::new(&pattern).map_err(Box::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migemo::compact_dictionary::CompactDictionary;
+    use crate::migemo::ternary_regex_generator::TernaryRegexGenerator;
+    use crate::migemo::test_support::build_dict;
+    use regex_automata::Input;
+
+    fn kensaku_dict() -> CompactDictionary {
+        build_dict(&[("けんさく", &["検索", "研削"])])
+    }
+
+    #[test]
+    fn query_regex_matches_candidates() {
+        let dict = kensaku_dict();
+        let regex = query_regex("kensaku", &dict).unwrap();
+        for text in [
+            "kensaku",
+            "けんさく",
+            "ケンサク",
+            "検索",
+            "研削",
+            "ｋｅｎｓａｋｕ",
+            "ｹﾝｻｸ",
+        ] {
+            assert!(regex.is_match(text), "{}", text);
+        }
+        assert!(!regex.is_match("検査"));
+
+        let dfa = query_dfa("kensaku", &dict).unwrap();
+        let found = dfa.find(Input::new("この検索は速い".as_bytes())).unwrap();
+        assert_eq!(&"この検索は速い"[found.start()..found.end()], "検索");
+    }
+
+    #[test]
+    fn rust_operator_escapes_all_metacharacters() {
+        // 集合演算や範囲になり得る文字を含め、ASCIIの記号をすべて試す
+        let symbols: Vec<char> = (0x21u8..0x7F)
+            .map(|b| b as char)
+            .filter(|c| !c.is_ascii_alphanumeric())
+            .collect();
+        for op in [RegexOperator::Rust, RegexOperator::RustNonNewline] {
+            let mut generator = TernaryRegexGenerator::new();
+            for c in &symbols {
+                generator.add(&[*c]);
+                generator.add(&[*c, *c, 'x']);
+            }
+            let pattern = generator.generate(&op);
+            let regex = Regex::new(&pattern).unwrap();
+            for c in &symbols {
+                let single = c.to_string();
+                assert!(regex.find(&single).is_some_and(|m| m.as_str() == single), "{}", single);
+            }
+            // 文字クラスの中の`-`が範囲として解釈されていないこと
+            assert!(!regex.is_match("0"));
+        }
+        let regex = Regex::new(&query_with_options(
+            "a-b".to_string(),
+            &kensaku_dict(),
+            &RegexOperator::Rust,
+            &QueryOptions::default(),
+        ))
+        .unwrap();
+        assert!(regex.is_match("a-b"));
+        assert!(regex.is_match("あーぶ"));
+        assert!(!regex.is_match("a.b"));
+    }
+}
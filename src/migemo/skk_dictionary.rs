@@ -0,0 +1,254 @@
+use super::compact_dictionary_builder::encode_char;
+use super::text_dictionary::{TextDictionaryError, TextEncoding, decode_text};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// 送りありエントリ (`かk /書/` など) の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OkuriAriPolicy {
+    /// 送りありエントリを取り込まない
+    Drop,
+    /// 送り仮名を除いた語幹を読みとして取り込む (`か` → `書`)
+    Stem,
+    /// 送り仮名の子音から考えられる仮名を補って取り込む (`かく` → `書く`、`かき` → `書き`、...)
+    Expand,
+}
+
+impl FromStr for OkuriAriPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OkuriAriPolicy, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "drop" => Ok(OkuriAriPolicy::Drop),
+            "stem" => Ok(OkuriAriPolicy::Stem),
+            "expand" => Ok(OkuriAriPolicy::Expand),
+            _ => Err(format!("unsupported okuri-ari policy: {}", s)),
+        }
+    }
+}
+
+/// SKK辞書の取り込みで、読み飛ばしたものの記録
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SkkImportReport {
+    /// 取り込んだエントリの数
+    pub imported_entries: usize,
+    /// 辞書ファイルで表現できない文字を含むため、読み飛ばした読み
+    pub skipped_readings: Vec<String>,
+    /// 方針に従って取り込まなかった送りありエントリの数
+    pub dropped_okuri_ari: usize,
+    /// Lispの式などで、読み飛ばした候補の数
+    pub skipped_candidates: usize,
+    /// 形式が正しくないため、読み飛ばした行の番号 (1始まり)
+    pub malformed_lines: Vec<usize>,
+}
+
+/// 送り仮名の子音に対応する仮名
+fn okurigana_for(consonant: char) -> &'static [&'static str] {
+    match consonant {
+        'a' => &["あ"],
+        'i' => &["い"],
+        'u' => &["う"],
+        'e' => &["え"],
+        'o' => &["お"],
+        'k' => &["か", "き", "く", "け", "こ"],
+        'g' => &["が", "ぎ", "ぐ", "げ", "ご"],
+        's' => &["さ", "し", "す", "せ", "そ"],
+        'z' => &["ざ", "じ", "ず", "ぜ", "ぞ"],
+        'j' => &["じ"],
+        't' => &["た", "ち", "つ", "て", "と", "っ"],
+        'c' => &["ち"],
+        'd' => &["だ", "ぢ", "づ", "で", "ど"],
+        'n' => &["な", "に", "ぬ", "ね", "の", "ん"],
+        'h' => &["は", "ひ", "ふ", "へ", "ほ"],
+        'f' => &["ふ"],
+        'b' => &["ば", "び", "ぶ", "べ", "ぼ"],
+        'p' => &["ぱ", "ぴ", "ぷ", "ぺ", "ぽ"],
+        'm' => &["ま", "み", "む", "め", "も"],
+        'y' => &["や", "ゆ", "よ"],
+        'r' => &["ら", "り", "る", "れ", "ろ"],
+        'w' => &["わ", "を"],
+        _ => &[],
+    }
+}
+
+fn is_representable(reading: &str) -> bool {
+    reading.chars().all(|c| encode_char(c).is_some())
+}
+
+fn insert_candidates(dict: &mut HashMap<String, Vec<String>>, reading: String, candidates: &[String]) {
+    let values = dict.entry(reading).or_default();
+    for candidate in candidates {
+        if !values.contains(candidate) {
+            values.push(candidate.clone());
+        }
+    }
+}
+
+/// 候補の一覧を、送り仮名ごとのブロック (`[く/書/]`) と、それ以外に分ける
+fn parse_candidates(body: &str, report: &mut SkkImportReport) -> (Vec<String>, Vec<(String, Vec<String>)>) {
+    let mut candidates = Vec::new();
+    let mut blocks: Vec<(String, Vec<String>)> = Vec::new();
+    let mut in_block = false;
+    for field in body.split('/') {
+        if let Some(okurigana) = field.strip_prefix('[') {
+            blocks.push((okurigana.to_string(), Vec::new()));
+            in_block = true;
+            continue;
+        }
+        if field == "]" {
+            in_block = false;
+            continue;
+        }
+        if field.is_empty() {
+            continue;
+        }
+        // 注釈を取り除く
+        let candidate = field.split(';').next().unwrap_or_default();
+        // Lispの式は評価できないので読み飛ばす
+        if candidate.is_empty() || candidate.starts_with('(') {
+            report.skipped_candidates += 1;
+            continue;
+        }
+        match blocks.last_mut() {
+            Some((_, block)) if in_block => block.push(candidate.to_string()),
+            _ => candidates.push(candidate.to_string()),
+        }
+    }
+    (candidates, blocks)
+}
+
+/// SKK辞書 (SKK-JISYO) を、`compact_dictionary_builder::build`に渡せる形式で読み込む
+///
+/// 候補の注釈は取り除き、Lispの式による候補は読み飛ばす。
+/// 辞書ファイルで表現できない読みは読み飛ばし、標準出力には何も出力せずに記録として返す。
+pub fn parse_skk_jisyo(text: &str, policy: OkuriAriPolicy) -> (HashMap<String, Vec<String>>, SkkImportReport) {
+    let mut dict: HashMap<String, Vec<String>> = HashMap::new();
+    let mut report = SkkImportReport::default();
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let Some((reading, body)) = line.split_once(" /") else {
+            report.malformed_lines.push(line_index + 1);
+            continue;
+        };
+        // 接頭辞・接尾辞を表す`>`は読みに含めない
+        let reading = reading.trim_matches('>');
+        let (candidates, blocks) = parse_candidates(body, &mut report);
+        if candidates.is_empty() && blocks.is_empty() {
+            continue;
+        }
+
+        // 読みが「仮名 + 英小文字」で終わるものは送りありエントリ
+        let mut chars = reading.chars();
+        let okuri = match (chars.next_back(), chars.as_str()) {
+            (Some(c), stem) if c.is_ascii_lowercase() && !stem.is_empty() && !stem.is_ascii() => Some((stem, c)),
+            _ => None,
+        };
+        let mut entries: Vec<(String, Vec<String>)> = Vec::new();
+        match (okuri, policy) {
+            (None, _) => entries.push((reading.to_string(), candidates)),
+            (Some(_), OkuriAriPolicy::Drop) => {
+                report.dropped_okuri_ari += 1;
+                continue;
+            }
+            (Some((stem, _)), OkuriAriPolicy::Stem) => {
+                let mut all = candidates;
+                for (_, block) in blocks {
+                    all.extend(block);
+                }
+                entries.push((stem.to_string(), all));
+            }
+            (Some((stem, consonant)), OkuriAriPolicy::Expand) => {
+                // 送り仮名が明示されたブロックを優先し、残りは子音から補う
+                for (okurigana, block) in &blocks {
+                    let words = block.iter().map(|c| format!("{}{}", c, okurigana)).collect();
+                    entries.push((format!("{}{}", stem, okurigana), words));
+                }
+                for okurigana in okurigana_for(consonant) {
+                    let words = candidates.iter().map(|c| format!("{}{}", c, okurigana)).collect();
+                    entries.push((format!("{}{}", stem, okurigana), words));
+                }
+                if entries.is_empty() {
+                    report.dropped_okuri_ari += 1;
+                    continue;
+                }
+            }
+        }
+
+        if !is_representable(reading) {
+            report.skipped_readings.push(reading.to_string());
+            continue;
+        }
+        for (key, words) in entries {
+            if !key.is_empty() && !words.is_empty() {
+                insert_candidates(&mut dict, key, &words);
+            }
+        }
+        report.imported_entries += 1;
+    }
+    (dict, report)
+}
+
+/// SKK辞書ファイルのバイト列を、文字コードを指定して読み込む
+pub fn read_skk_jisyo(
+    bytes: &[u8],
+    encoding: TextEncoding,
+    policy: OkuriAriPolicy,
+) -> Result<(HashMap<String, Vec<String>>, SkkImportReport), TextDictionaryError> {
+    let text = decode_text(bytes, encoding)?;
+    Ok(parse_skk_jisyo(&text, policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKK_JISYO: &str = ";; -*- mode: fundamental; coding: utf-8 -*-\n\
+        ;; okuri-ari entries.\n\
+        かk /書/描;picture/\n\
+        おおk /多/大/[く/多/大/]/[き/大/]/\n\
+        ;; okuri-nasi entries.\n\
+        けんさく /検索/研削;grinding/(concat \"abc\")/\n\
+        お> /御/\n\
+        ゐど /井戸/\n\
+        こわれたぎょう\n";
+
+    #[test]
+    fn parse_skk_jisyo_strips_annotations() {
+        let (dict, report) = parse_skk_jisyo(SKK_JISYO, OkuriAriPolicy::Drop);
+        assert_eq!(dict["けんさく"], vec!["検索", "研削"]);
+        assert_eq!(dict["お"], vec!["御"]);
+        assert_eq!(dict.len(), 3);
+        assert_eq!(report.imported_entries, 3);
+        assert_eq!(report.dropped_okuri_ari, 2);
+        assert_eq!(report.skipped_candidates, 1);
+        assert_eq!(report.malformed_lines, vec![9]);
+        assert!(report.skipped_readings.is_empty());
+    }
+
+    #[test]
+    fn parse_skk_jisyo_keeps_stem() {
+        let (dict, report) = parse_skk_jisyo(SKK_JISYO, OkuriAriPolicy::Stem);
+        assert_eq!(dict["か"], vec!["書", "描"]);
+        assert_eq!(dict["おお"], vec!["多", "大"]);
+        assert_eq!(report.dropped_okuri_ari, 0);
+    }
+
+    #[test]
+    fn parse_skk_jisyo_expands_okurigana() {
+        let (dict, _) = parse_skk_jisyo(SKK_JISYO, OkuriAriPolicy::Expand);
+        assert_eq!(dict["かく"], vec!["書く", "描く"]);
+        assert_eq!(dict["かこ"], vec!["書こ", "描こ"]);
+        assert_eq!(dict["おおく"], vec!["多く", "大く"]);
+        assert_eq!(dict["おおき"], vec!["大き", "多き"]);
+    }
+
+    #[test]
+    fn parse_skk_jisyo_reports_unrepresentable_readings() {
+        let (dict, report) = parse_skk_jisyo("ケンサク /検索/\nけんさ /検査/\n", OkuriAriPolicy::Drop);
+        assert_eq!(dict.len(), 1);
+        assert_eq!(report.skipped_readings, vec!["ケンサク"]);
+    }
+}
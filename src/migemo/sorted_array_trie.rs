@@ -19,6 +19,36 @@ impl SortedArrayTrie {
         self.keys.get(index).map(Vec::as_slice)
     }
 
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// キーを追加し、その位置を返す。すでに存在する場合は`Err`で位置を返す。
+    ///
+    /// 追加した位置より後ろのキーは、位置が1つずつ後ろにずれる。
+    pub fn insert(&mut self, key: Vec<u16>) -> Result<usize, usize> {
+        match self.keys.binary_search(&key) {
+            Ok(index) => Err(index),
+            Err(index) => {
+                self.keys.insert(index, key);
+                Ok(index)
+            }
+        }
+    }
+
+    /// キーを削除し、削除する前の位置を返す
+    ///
+    /// 削除した位置より後ろのキーは、位置が1つずつ前にずれる。
+    pub fn remove(&mut self, key: &[u16]) -> Option<usize> {
+        let index = self.exact_search(key)?;
+        self.keys.remove(index);
+        Some(index)
+    }
+
     pub fn common_prefix_search(&self, key: &[u16]) -> Vec<usize> {
         let mut result = Vec::new();
         let mut start = 0;
@@ -144,4 +174,21 @@ mod tests {
 
         assert!(words.is_empty());
     }
+
+    #[test]
+    fn insert_and_remove_keep_keys_sorted() {
+        let mut dict = SortedArrayTrie::build(vec![utf16("けん"), utf16("けんさく")]);
+
+        assert_eq!(dict.insert(utf16("けんさ")), Ok(1));
+        assert_eq!(dict.insert(utf16("けんさ")), Err(1));
+        assert_eq!(
+            decode_hits(&dict, dict.predictive_search(&utf16("け"))),
+            vec!["けん", "けんさ", "けんさく"]
+        );
+
+        assert_eq!(dict.remove(&utf16("けん")), Some(0));
+        assert_eq!(dict.remove(&utf16("けん")), None);
+        assert_eq!(dict.len(), 2);
+        assert_eq!(dict.exact_search(&utf16("けんさく")), Some(1));
+    }
 }
@@ -99,32 +99,6 @@ fn traverse_siblings<'a>(node: &'a Option<Box<TernaryRegexNode>>, buffer: &mut V
     }
 }
 
-const ESCAPE_BITMAP: [u64; 2] = generate_escape_bitmap();
-
-const fn generate_escape_bitmap() -> [u64; 2] {
-    const ESCAPE_CHARS: &str = r"()[]{}?*+|^$.\";
-    let mut bitmap = [0u64; 2];
-    let bytes = ESCAPE_CHARS.as_bytes();
-    let mut i = 0;
-    while i < bytes.len() {
-        let byte = bytes[i];
-        let c = byte as u64;
-        if c < 128 {
-            bitmap[(c / 64) as usize] |= 1 << (c % 64);
-        }
-        i += 1;
-    }
-    bitmap
-}
-
-fn is_characters_to_escape(c: char) -> bool {
-    if c < '\u{80}' {
-        return (ESCAPE_BITMAP[(c as usize) / 64] >> ((c as u64) % 64)) & 1 == 1;
-    } else {
-        return false;
-    }
-}
-
 fn generate(node: &Option<Box<TernaryRegexNode>>, buffer: &mut String, op: &RegexOperatorDetail) {
     let mut brother = 0;
     let mut haschild = 0;
@@ -141,20 +115,11 @@ fn generate(node: &Option<Box<TernaryRegexNode>>, buffer: &mut String, op: &Rege
         buffer.push_str(&op.begin_group);
     }
     if nochild > 0 {
+        let chars: Vec<char> = siblings.iter().filter(|n| n.child.is_none()).map(|n| n.code).collect();
         if nochild > 1 {
-            buffer.push_str(&op.begin_class);
-        }
-        for n in &siblings {
-            if n.child.is_some() {
-                continue;
-            }
-            if is_characters_to_escape(n.code) {
-                buffer.push('\\');
-            }
-            buffer.push(n.code);
-        }
-        if nochild > 1 {
-            buffer.push_str(&op.end_class);
+            op.push_class(&chars, buffer);
+        } else {
+            op.push_literal(chars[0], buffer);
         }
     }
     if haschild > 0 {
@@ -167,10 +132,7 @@ fn generate(node: &Option<Box<TernaryRegexNode>>, buffer: &mut String, op: &Rege
                 if !is_first {
                     buffer.push_str(&op.or);
                 }
-                if is_characters_to_escape(n.code) {
-                    buffer.push('\\');
-                }
-                buffer.push(n.code);
+                op.push_literal(n.code, buffer);
                 if !op.newline.is_empty() {
                     buffer.push_str(&op.newline);
                 }
@@ -216,13 +178,12 @@ impl RegexGeneratorTrait for TernaryRegexGenerator {
         self.root = insert(word, 0, self.root.take());
     }
 
-    fn generate(&self, op: &RegexOperator) -> String {
+    fn generate_with_detail(&self, op: &RegexOperatorDetail) -> String {
         if self.root.is_none() {
             return String::new();
         } else {
-            let op_detail = RegexOperatorDetail::get_regex_operator_detail(op);
             let mut buffer = String::new();
-            generate(&self.root, &mut buffer, &op_detail);
+            generate(&self.root, &mut buffer, op);
             return buffer;
         }
     }
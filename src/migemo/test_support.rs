@@ -0,0 +1,18 @@
+// 単体テストで共通に使う辞書の組み立て
+
+use super::compact_dictionary::CompactDictionary;
+use super::compact_dictionary_builder::build;
+use std::collections::HashMap;
+
+/// 読みと単語の組から、`build`に渡す辞書の内容を作る
+pub(crate) fn dict_entries(entries: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+    entries
+        .iter()
+        .map(|(key, words)| (key.to_string(), words.iter().map(|w| w.to_string()).collect()))
+        .collect()
+}
+
+/// 読みと単語の組から、ヘッダーのない辞書を作る
+pub(crate) fn build_dict(entries: &[(&str, &[&str])]) -> CompactDictionary {
+    CompactDictionary::new(&build(dict_entries(entries)))
+}
@@ -0,0 +1,234 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// テキスト形式の辞書ファイルの文字コード
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    #[cfg(feature = "encoding")]
+    EucJp,
+    /// UTF-8として読めればUTF-8、読めなければEUC-JPとして扱う。
+    /// `encoding`フィーチャーが無効な場合はUTF-8のみを受け付ける。
+    Auto,
+}
+
+impl FromStr for TextEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TextEncoding, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(TextEncoding::Utf8),
+            #[cfg(feature = "encoding")]
+            "euc-jp" | "eucjp" => Ok(TextEncoding::EucJp),
+            "auto" => Ok(TextEncoding::Auto),
+            _ => Err(format!("unsupported encoding: {}", s)),
+        }
+    }
+}
+
+/// テキスト形式の辞書ファイルの読み込みに失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextDictionaryError {
+    /// 指定した文字コードとして読めないバイト列が含まれている
+    InvalidEncoding { encoding: TextEncoding },
+}
+
+impl fmt::Display for TextDictionaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextDictionaryError::InvalidEncoding { encoding } => {
+                write!(f, "input is not valid {:?} text", encoding)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextDictionaryError {}
+
+/// バイト列を、指定した文字コードの文字列として読む
+pub fn decode_text(bytes: &[u8], encoding: TextEncoding) -> Result<Cow<'_, str>, TextDictionaryError> {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match encoding {
+        TextEncoding::Utf8 => std::str::from_utf8(bytes)
+            .map(Cow::Borrowed)
+            .map_err(|_| TextDictionaryError::InvalidEncoding { encoding }),
+        #[cfg(feature = "encoding")]
+        TextEncoding::EucJp => encoding_rs::EUC_JP
+            .decode_without_bom_handling_and_without_replacement(bytes)
+            .ok_or(TextDictionaryError::InvalidEncoding { encoding }),
+        TextEncoding::Auto => match std::str::from_utf8(bytes) {
+            Ok(text) => Ok(Cow::Borrowed(text)),
+            #[cfg(feature = "encoding")]
+            Err(_) => decode_text(bytes, TextEncoding::EucJp),
+            #[cfg(not(feature = "encoding"))]
+            Err(_) => Err(TextDictionaryError::InvalidEncoding { encoding }),
+        },
+    }
+}
+
+/// C/Migemoの辞書 (migemo-dict) を読み込む
+///
+/// 1行に1つの読みと、それに続くタブ区切りの単語を記述する。`;`で始まる行はコメントとして読み飛ばす。
+/// 同じ読みが複数回現れた場合は、出現順に単語を追加し、重複する単語は取り除く。
+pub fn parse_migemo_dict(text: &str) -> HashMap<String, Vec<String>> {
+    let mut dict: HashMap<String, Vec<String>> = HashMap::new();
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let mut fields = line.split('\t');
+        let key = fields.next().unwrap_or_default();
+        if key.is_empty() {
+            continue;
+        }
+        let words: Vec<&str> = fields.filter(|w| !w.is_empty()).collect();
+        if words.is_empty() {
+            continue;
+        }
+        let values = dict.entry(key.to_string()).or_default();
+        for word in words {
+            if !values.iter().any(|v| v == word) {
+                values.push(word.to_string());
+            }
+        }
+    }
+    dict
+}
+
+/// C/Migemoの辞書ファイルのバイト列を、文字コードを指定して読み込む
+pub fn read_migemo_dict(
+    bytes: &[u8],
+    encoding: TextEncoding,
+) -> Result<HashMap<String, Vec<String>>, TextDictionaryError> {
+    let text = decode_text(bytes, encoding)?;
+    Ok(parse_migemo_dict(&text))
+}
+
+/// 単語の重み (出現頻度など) の一覧を読み込む
+///
+/// 1行に1つの単語と、タブで区切った重みを記述する。`;`で始まる行と、重みが数値でない行は読み飛ばす。
+/// 同じ単語が複数回現れた場合は、大きいほうの重みを使う。
+pub fn parse_word_weights(text: &str) -> HashMap<String, u32> {
+    let mut weights: HashMap<String, u32> = HashMap::new();
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let Some((word, weight)) = line.split_once('\t') else {
+            continue;
+        };
+        let Ok(weight) = weight.trim().parse::<u32>() else {
+            continue;
+        };
+        if word.is_empty() {
+            continue;
+        }
+        let entry = weights.entry(word.to_string()).or_default();
+        *entry = (*entry).max(weight);
+    }
+    weights
+}
+
+/// 読みと単語の一覧を、C/Migemoの辞書 (migemo-dict) の形式でUTF-8で書き出す
+pub fn write_migemo_dict<W: Write, I: IntoIterator<Item = (String, Vec<String>)>>(
+    writer: &mut W,
+    entries: I,
+) -> io::Result<()> {
+    for (key, words) in entries {
+        writer.write_all(key.as_bytes())?;
+        for word in words {
+            writer.write_all(b"\t")?;
+            writer.write_all(word.as_bytes())?;
+        }
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migemo::compact_dictionary::CompactDictionary;
+    use crate::migemo::compact_dictionary_builder::build;
+
+    const MIGEMO_DICT: &str = "; コメント\n\
+        けんさ\t検査\n\
+        けんさく\t検索\t研削\n\
+        \n\
+        けんさく\t検索\t献策\r\n\
+        みだしのみ\n";
+
+    #[test]
+    fn parse_migemo_dict_merges_entries() {
+        let dict = parse_migemo_dict(MIGEMO_DICT);
+        assert_eq!(dict.len(), 2);
+        assert_eq!(dict["けんさ"], vec!["検査"]);
+        assert_eq!(dict["けんさく"], vec!["検索", "研削", "献策"]);
+    }
+
+    #[test]
+    fn read_migemo_dict_feeds_builder() {
+        let dict = read_migemo_dict(MIGEMO_DICT.as_bytes(), TextEncoding::Utf8).unwrap();
+        let buffer = build(dict);
+        let compact_dict = CompactDictionary::new(&buffer);
+        let key: Vec<u16> = "けんさく".encode_utf16().collect();
+        let result: Vec<String> = compact_dict
+            .search(&key)
+            .map(|s| String::from_utf16(&s).unwrap())
+            .collect();
+        assert_eq!(result, vec!["検索", "研削", "献策"]);
+    }
+
+    #[test]
+    fn parse_word_weights_skips_malformed_lines() {
+        let weights = parse_word_weights("; コメント\n検索\t120\n罠索\t1\r\n検索\t80\n研削\tmany\n献策\n");
+        assert_eq!(weights.len(), 2);
+        assert_eq!(weights["検索"], 120);
+        assert_eq!(weights["罠索"], 1);
+    }
+
+    #[test]
+    fn decode_text_rejects_invalid_utf8() {
+        assert_eq!(
+            decode_text(b"\xA4\xB1\tabc", TextEncoding::Utf8),
+            Err(TextDictionaryError::InvalidEncoding {
+                encoding: TextEncoding::Utf8
+            })
+        );
+        assert_eq!(decode_text(b"\xEF\xBB\xBFabc", TextEncoding::Utf8).unwrap(), "abc");
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn decode_text_reads_euc_jp() {
+        // 「けんさ\t検査」をEUC-JPで表したもの
+        let bytes = b"\xA4\xB1\xA4\xF3\xA4\xB5\t\xB8\xA1\xBA\xBA\n";
+        let dict = read_migemo_dict(bytes, TextEncoding::EucJp).unwrap();
+        assert_eq!(dict["けんさ"], vec!["検査"]);
+        let dict = read_migemo_dict(bytes, TextEncoding::Auto).unwrap();
+        assert_eq!(dict["けんさ"], vec!["検査"]);
+    }
+
+    #[test]
+    fn write_migemo_dict_round_trip() {
+        let dict = parse_migemo_dict(MIGEMO_DICT);
+        let mut entries: Vec<(String, Vec<String>)> = dict.clone().into_iter().collect();
+        entries.sort();
+        let mut output = Vec::new();
+        write_migemo_dict(&mut output, entries).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert_eq!(text, "けんさ\t検査\nけんさく\t検索\t研削\t献策\n");
+
+        let compact_dict = CompactDictionary::new(&build(parse_migemo_dict(&text)));
+        let mut dumped: Vec<(String, Vec<String>)> = compact_dict.entries().collect();
+        dumped.sort();
+        let mut output = Vec::new();
+        write_migemo_dict(&mut output, dumped).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), text);
+    }
+}
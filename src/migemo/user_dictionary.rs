@@ -0,0 +1,313 @@
+use super::compact_dictionary::FuzzyMatch;
+use super::dictionary::Dictionary;
+use super::sorted_array_trie::SortedArrayTrie;
+use super::text_dictionary::{parse_migemo_dict, write_migemo_dict};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// 実行中に単語を追加・削除できる、メモリ上のユーザー辞書
+///
+/// 読みを`SortedArrayTrie`で管理し、読みごとの単語を同じ位置に並べた配列で保持する。
+/// 保存した辞書は`compact_dictionary_builder::build`で辞書ファイルにできる。
+#[derive(Debug, Default)]
+pub struct UserDictionary {
+    readings: SortedArrayTrie,
+    // readingsと同じ順に並べた、読みごとの単語
+    words: Vec<Vec<Vec<u16>>>,
+}
+
+impl UserDictionary {
+    pub fn new() -> UserDictionary {
+        UserDictionary::default()
+    }
+
+    /// 読みと単語の組から辞書を作る
+    pub fn from_map(dict: HashMap<String, Vec<String>>) -> UserDictionary {
+        let mut user_dictionary = UserDictionary::new();
+        for (reading, words) in dict {
+            for word in words {
+                user_dictionary.insert(&reading, &word);
+            }
+        }
+        user_dictionary
+    }
+
+    /// 単語を追加する。すでに登録されている場合は`false`を返す。
+    ///
+    /// `save`した辞書を`load`で同じ内容に読み戻せるように、タブや改行を含む読みと単語、
+    /// コメントと区別できない`;`で始まる読みも追加せずに`false`を返す。
+    pub fn insert(&mut self, reading: &str, word: &str) -> bool {
+        let is_invalid = |text: &str| text.is_empty() || text.contains(['\t', '\n', '\r']);
+        if is_invalid(reading) || is_invalid(word) || reading.starts_with(';') {
+            return false;
+        }
+        let word: Vec<u16> = word.encode_utf16().collect();
+        let index = match self.readings.insert(reading.encode_utf16().collect()) {
+            Ok(index) => {
+                self.words.insert(index, Vec::new());
+                index
+            }
+            Err(index) => index,
+        };
+        if self.words[index].contains(&word) {
+            return false;
+        }
+        self.words[index].push(word);
+        true
+    }
+
+    /// 単語を削除する。登録されていなかった場合は`false`を返す。
+    pub fn remove(&mut self, reading: &str, word: &str) -> bool {
+        let reading: Vec<u16> = reading.encode_utf16().collect();
+        let word: Vec<u16> = word.encode_utf16().collect();
+        let Some(index) = self.readings.exact_search(&reading) else {
+            return false;
+        };
+        let Some(position) = self.words[index].iter().position(|w| *w == word) else {
+            return false;
+        };
+        self.words[index].remove(position);
+        // 単語がなくなった読みは取り除く
+        if self.words[index].is_empty() {
+            self.readings.remove(&reading);
+            self.words.remove(index);
+        }
+        true
+    }
+
+    /// 登録されている読みの数
+    pub fn len(&self) -> usize {
+        self.readings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.readings.is_empty()
+    }
+
+    pub fn search<'a>(&'a self, key: &[u16]) -> impl Iterator<Item = Vec<u16>> + 'a {
+        let words = match self.readings.exact_search(key) {
+            Some(index) => self.words[index].as_slice(),
+            None => &[],
+        };
+        words.iter().cloned()
+    }
+
+    pub fn predictive_search<'a>(&'a self, key: &[u16]) -> impl Iterator<Item = Vec<u16>> + 'a {
+        self.readings
+            .predictive_search(key)
+            .into_iter()
+            .flat_map(move |index| self.words[index].iter().cloned())
+    }
+
+    /// 読みとの編集距離が`max_edits`以下の読みを持つ単語を、編集距離の小さい順に返す
+    ///
+    /// `CompactDictionary::fuzzy_search_weighted`と同じ条件で引く。ユーザー辞書は小さいので、読みをすべて調べる。
+    pub fn fuzzy_search(&self, key: &[u16], max_edits: usize, predictive: bool) -> Vec<FuzzyMatch> {
+        if key.len() <= max_edits {
+            return Vec::new();
+        }
+        let mut readings: Vec<(usize, usize)> = (0..self.readings.len())
+            .filter_map(|index| {
+                let distance = edit_distance(key, self.readings.get(index).unwrap());
+                (distance <= max_edits).then_some((index, distance))
+            })
+            .collect();
+        readings.sort_by_key(|&(_, distance)| distance);
+        // 複数の近い読みから同じ読みに届いた場合は、編集距離の小さいほうだけを使う
+        let mut seen = vec![false; self.readings.len()];
+        let mut matches = Vec::new();
+        for (index, distance) in readings {
+            let reading = self.readings.get(index).unwrap();
+            let indices = if predictive && reading.len() >= key.len() {
+                self.readings.predictive_search(reading)
+            } else {
+                vec![index]
+            };
+            for index in indices {
+                if std::mem::replace(&mut seen[index], true) {
+                    continue;
+                }
+                for word in &self.words[index] {
+                    matches.push(FuzzyMatch {
+                        word: word.clone(),
+                        weight: 0,
+                        key: reading.to_vec(),
+                        distance,
+                    });
+                }
+            }
+        }
+        matches
+    }
+
+    /// 読みごとの単語の一覧を返す。`compact_dictionary_builder::build`にそのまま渡せる。
+    pub fn to_map(&self) -> HashMap<String, Vec<String>> {
+        self.entries().collect()
+    }
+
+    /// 読みの順に、読みと単語の一覧を返す
+    pub fn entries(&self) -> impl Iterator<Item = (String, Vec<String>)> + '_ {
+        (0..self.readings.len()).map(move |index| {
+            let reading = String::from_utf16_lossy(self.readings.get(index).unwrap());
+            let words = self.words[index].iter().map(|w| String::from_utf16_lossy(w)).collect();
+            (reading, words)
+        })
+    }
+
+    /// C/Migemoの辞書 (migemo-dict) の形式で保存する
+    pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_migemo_dict(writer, self.entries())
+    }
+
+    /// `save`で保存した辞書を読み込む
+    pub fn load(text: &str) -> UserDictionary {
+        UserDictionary::from_map(parse_migemo_dict(text))
+    }
+}
+
+impl Dictionary for UserDictionary {
+    fn search<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = Vec<u16>> + 'a> {
+        Box::new(UserDictionary::search(self, key))
+    }
+
+    fn predictive_search<'a>(&'a self, key: &[u16]) -> Box<dyn Iterator<Item = Vec<u16>> + 'a> {
+        Box::new(UserDictionary::predictive_search(self, key))
+    }
+
+    fn fuzzy_search_weighted<'a>(
+        &'a self,
+        key: &[u16],
+        max_edits: usize,
+        predictive: bool,
+    ) -> Box<dyn Iterator<Item = FuzzyMatch> + 'a> {
+        Box::new(UserDictionary::fuzzy_search(self, key, max_edits, predictive).into_iter())
+    }
+}
+
+// 挿入・削除・置換の回数で数えた編集距離
+fn edit_distance(a: &[u16], b: &[u16]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, &x) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &y) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(x != y);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j + 1] + 1).min(row[j] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migemo::compact_dictionary::CompactDictionary;
+    use crate::migemo::compact_dictionary_builder::build;
+    use crate::migemo::dictionary::LayeredDictionary;
+    use crate::migemo::test_support::build_dict;
+
+    fn utf16(text: &str) -> Vec<u16> {
+        text.encode_utf16().collect()
+    }
+
+    fn to_strings(words: impl Iterator<Item = Vec<u16>>) -> Vec<String> {
+        words.map(|w| String::from_utf16(&w).unwrap()).collect()
+    }
+
+    #[test]
+    fn insert_and_remove_words() {
+        let mut dict = UserDictionary::new();
+        assert!(dict.insert("けんさく", "検索"));
+        assert!(dict.insert("けんさく", "研削"));
+        assert!(!dict.insert("けんさく", "検索"));
+        assert!(dict.insert("けんさ", "検査"));
+        assert_eq!(dict.len(), 2);
+
+        assert_eq!(to_strings(dict.search(&utf16("けんさく"))), vec!["検索", "研削"]);
+        assert_eq!(
+            to_strings(dict.predictive_search(&utf16("けんさ"))),
+            vec!["検査", "検索", "研削"]
+        );
+
+        assert!(dict.remove("けんさく", "検索"));
+        assert!(!dict.remove("けんさく", "検索"));
+        assert!(dict.remove("けんさ", "検査"));
+        assert_eq!(dict.len(), 1);
+        assert_eq!(to_strings(dict.predictive_search(&utf16("けんさ"))), vec!["研削"]);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut dict = UserDictionary::new();
+        dict.insert("けんさく", "研削");
+        dict.insert("けんさく", "検索");
+        dict.insert("あい", "愛");
+        let mut output = Vec::new();
+        dict.save(&mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert_eq!(text, "あい\t愛\nけんさく\t研削\t検索\n");
+
+        let loaded = UserDictionary::load(&text);
+        assert_eq!(loaded.to_map(), dict.to_map());
+        assert_eq!(to_strings(loaded.search(&utf16("けんさく"))), vec!["研削", "検索"]);
+    }
+
+    #[test]
+    fn insert_rejects_text_that_does_not_round_trip() {
+        let mut dict = UserDictionary::new();
+        assert!(!dict.insert("a\tb", "x"));
+        assert!(!dict.insert("けんさく", "検\n索"));
+        assert!(!dict.insert("けんさく", "検索\r"));
+        assert!(!dict.insert(";けんさく", "検索"));
+        assert!(dict.insert("けんさく", "検索"));
+        let mut output = Vec::new();
+        dict.save(&mut output).unwrap();
+        let loaded = UserDictionary::load(&String::from_utf8(output).unwrap());
+        assert_eq!(loaded.to_map(), dict.to_map());
+    }
+
+    #[test]
+    fn fuzzy_search_tolerates_typos() {
+        let mut dict = UserDictionary::new();
+        dict.insert("けんさく", "検索");
+        dict.insert("けんさくき", "検索機");
+        dict.insert("か", "蚊");
+        let search = |key: &str, max_edits: usize, predictive: bool| -> Vec<(String, usize)> {
+            dict.fuzzy_search(&utf16(key), max_edits, predictive)
+                .into_iter()
+                .map(|m| (String::from_utf16(&m.word).unwrap(), m.distance))
+                .collect()
+        };
+        assert_eq!(search("けんすく", 1, false), vec![("検索".to_string(), 1)]);
+        assert_eq!(
+            search("けんすく", 1, true),
+            vec![("検索".to_string(), 1), ("検索機".to_string(), 1)]
+        );
+        assert_eq!(search("けんさく", 1, false)[0], ("検索".to_string(), 0));
+        assert_eq!(search("さ", 1, true), Vec::new());
+        assert_eq!(edit_distance(&utf16("けんさく"), &utf16("けさく")), 1);
+        assert_eq!(edit_distance(&utf16(""), &utf16("けん")), 2);
+    }
+
+    #[test]
+    fn fuzzy_search_through_layers() {
+        let mut user = UserDictionary::new();
+        user.insert("けんさく", "献策");
+        let mut layered = LayeredDictionary::new();
+        layered.push(user);
+        layered.push(build_dict(&[("けんさく", &["検索"])]));
+        let words: Vec<String> = Dictionary::fuzzy_search_weighted(&layered, &utf16("けんすく"), 1, false)
+            .map(|m| String::from_utf16(&m.word).unwrap())
+            .collect();
+        assert_eq!(words, vec!["献策", "検索"]);
+    }
+
+    #[test]
+    fn to_map_feeds_builder() {
+        let mut dict = UserDictionary::new();
+        dict.insert("けんさく", "検索");
+        let compact_dict = CompactDictionary::new(&build(dict.to_map()));
+        assert_eq!(to_strings(compact_dict.search(&utf16("けんさく"))), vec!["検索"]);
+    }
+}